@@ -0,0 +1,237 @@
+use image::{Rgb, RgbImage};
+
+use crate::XyXy;
+use crate::tags::TagDetection;
+use crate::tracker::Track;
+use crate::yolo::{Detection, ObjectMask};
+
+const DETECTION_COLOR: Rgb<u8> = Rgb([255, 200, 0]);
+const TRACK_COLOR: Rgb<u8> = Rgb([0, 255, 0]);
+const TAG_COLOR: Rgb<u8> = Rgb([0, 160, 255]);
+const WINDOW_COLOR: Rgb<u8> = Rgb([255, 0, 255]);
+const TEXT_BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+
+// font pixels are drawn as squares of this size
+const TEXT_SCALE: u32 = 2;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Everything found in one frame that is worth drawing
+pub struct Overlay<'a> {
+    /// raw detections, before the tracker
+    pub detections: &'a [Detection],
+    /// confirmed tracks matched in this frame
+    pub tracks: &'a [Track],
+    pub tags: &'a [TagDetection],
+    /// horizontal pixel ranges nav treats as "centered"
+    pub windows: &'a [(f32, f32)],
+}
+
+/// Draw boxes, labels, track ids, tags and the alignment windows onto a frame
+pub fn draw(img: &mut RgbImage, overlay: &Overlay) {
+    for &(left, right) in overlay.windows {
+        vertical_line(img, left, WINDOW_COLOR);
+        vertical_line(img, right, WINDOW_COLOR);
+    }
+
+    for detection in overlay.detections {
+        if let Some(mask) = &detection.mask {
+            tint(img, mask, DETECTION_COLOR);
+        }
+        rectangle(img, detection.bbox, 1, DETECTION_COLOR);
+        let label = format!("{} {:.2}", detection.class_name, detection.confidence);
+        text(
+            img,
+            detection.bbox.0,
+            detection.bbox.1,
+            &label,
+            DETECTION_COLOR,
+        );
+    }
+
+    for track in overlay.tracks {
+        rectangle(img, track.bbox, 2, TRACK_COLOR);
+        let label = format!("#{}", track.id);
+        let below = track.bbox.3 + 2.0;
+        text(img, track.bbox.0, below, &label, TRACK_COLOR);
+    }
+
+    for tag in overlay.tags {
+        for n in 0..4 {
+            let (a, b) = (tag.corners[n], tag.corners[(n + 1) % 4]);
+            line(
+                img,
+                (a.0 as f32, a.1 as f32),
+                (b.0 as f32, b.1 as f32),
+                TAG_COLOR,
+            );
+        }
+
+        let label = match &tag.pose {
+            Some(pose) => format!("{} {:.2}m", tag.id, pose.range_bearing.range),
+            None => format!("{}", tag.id),
+        };
+        text(
+            img,
+            tag.center.0 as f32,
+            tag.center.1 as f32,
+            &label,
+            TAG_COLOR,
+        );
+    }
+}
+
+fn put(img: &mut RgbImage, x: i64, y: i64, color: Rgb<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < img.width() && (y as u32) < img.height() {
+        img.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+fn rectangle(img: &mut RgbImage, (x1, y1, x2, y2): XyXy, thickness: i64, color: Rgb<u8>) {
+    // keep the loops bounded for boxes hanging off the frame
+    let clamp_x = |x: f32| (x as i64).clamp(-1, img.width() as i64);
+    let clamp_y = |y: f32| (y as i64).clamp(-1, img.height() as i64);
+    let (x1, y1, x2, y2) = (clamp_x(x1), clamp_y(y1), clamp_x(x2), clamp_y(y2));
+
+    for t in 0..thickness {
+        for x in x1..=x2 {
+            put(img, x, y1 + t, color);
+            put(img, x, y2 - t, color);
+        }
+        for y in y1..=y2 {
+            put(img, x1 + t, y, color);
+            put(img, x2 - t, y, color);
+        }
+    }
+}
+
+/// Blend the color into every pixel of the mask
+fn tint(img: &mut RgbImage, mask: &ObjectMask, color: Rgb<u8>) {
+    let (width, height) = mask.pixels.dimensions();
+
+    for (x, y) in (0..height).flat_map(|y| (0..width).map(move |x| (x, y))) {
+        let (fx, fy) = (mask.origin.0 + x, mask.origin.1 + y);
+        if !mask.contains(x, y) || fx >= img.width() || fy >= img.height() {
+            continue;
+        }
+
+        let pixel = img.get_pixel_mut(fx, fy);
+        for (channel, tint) in pixel.0.iter_mut().zip(color.0) {
+            *channel = ((*channel as u16 + tint as u16) / 2) as u8;
+        }
+    }
+}
+
+fn vertical_line(img: &mut RgbImage, x: f32, color: Rgb<u8>) {
+    // dashed so boxes crossing it stay readable
+    for y in (0..img.height() as i64).filter(|y| y % 8 < 4) {
+        put(img, x as i64, y, color);
+    }
+}
+
+fn line(img: &mut RgbImage, a: (f32, f32), b: (f32, f32), color: Rgb<u8>) {
+    let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).ceil().max(1.0) as i64;
+
+    for n in 0..=steps {
+        let t = n as f32 / steps as f32;
+        let x = a.0 + (b.0 - a.0) * t;
+        let y = a.1 + (b.1 - a.1) * t;
+        put(img, x.round() as i64, y.round() as i64, color);
+    }
+}
+
+/// Text on a dark background so it reads on any frame, drawn above `y` when
+/// there is room
+fn text(img: &mut RgbImage, x: f32, y: f32, label: &str, color: Rgb<u8>) {
+    let char_width = (GLYPH_WIDTH + 1) * TEXT_SCALE;
+    let height = (GLYPH_HEIGHT + 2) * TEXT_SCALE;
+    let width = char_width * label.chars().count() as u32 + TEXT_SCALE;
+    let x = x.max(0.0) as i64;
+    let y = if y as i64 >= height as i64 {
+        y as i64 - height as i64
+    } else {
+        y as i64
+    };
+
+    for dy in 0..height as i64 {
+        for dx in 0..width as i64 {
+            put(img, x + dx, y + dy, TEXT_BACKGROUND);
+        }
+    }
+
+    for (n, c) in label.chars().enumerate() {
+        let origin_x = x + (TEXT_SCALE + n as u32 * char_width) as i64;
+        let origin_y = y + TEXT_SCALE as i64;
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+
+                for sy in 0..TEXT_SCALE {
+                    for sx in 0..TEXT_SCALE {
+                        put(
+                            img,
+                            origin_x + (col * TEXT_SCALE + sx) as i64,
+                            origin_y + (row as u32 * TEXT_SCALE + sy) as i64,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 5x7 bitmap of a character, one byte per row. Lowercase is drawn as
+/// uppercase and anything unknown as a question mark
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00; 7],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
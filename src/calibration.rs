@@ -0,0 +1,661 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+use image::{DynamicImage, GrayImage};
+use nalgebra::{DMatrix, DVector, Matrix3, Rotation3, SymmetricEigen, Vector3};
+use nokhwa::pixel_format::RgbFormat;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{self, CameraIntrinsics};
+use crate::checkerboard;
+use crate::error::Error;
+use crate::yolo;
+
+const USAGE: &str = "usage: calibrate <image dir | camera> [--cols 9] [--rows 6] \
+                     [--square 0.025] [--frames 20] [--out ../data/calibration.json]";
+
+// intrinsics refined by the optimiser: fx, fy, cx, cy, k1, k2, p1, p2
+const INTRINSIC_PARAMS: usize = 8;
+// per view extrinsics: rotation vector and translation
+const VIEW_PARAMS: usize = 6;
+// zhang's method needs three views to pin down the camera matrix
+const MIN_VIEWS: usize = 3;
+
+/// What gets written to the calibration file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationFile {
+    pub image_width: u32,
+    pub image_height: u32,
+    /// root mean square reprojection error in pixels
+    pub rms_error: f64,
+    pub views: usize,
+    pub intrinsics: CameraIntrinsics,
+}
+
+enum Source {
+    Directory(PathBuf),
+    Camera,
+}
+
+struct Args {
+    source: Source,
+    cols: usize,
+    rows: usize,
+    square: f64,
+    frames: usize,
+    out: PathBuf,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, Error> {
+    let invalid = || Error::CalibrationFailed(USAGE.to_string());
+
+    let source = match args.first().map(String::as_str) {
+        Some("camera") => Source::Camera,
+        Some(dir) => Source::Directory(PathBuf::from(dir)),
+        None => return Err(invalid()),
+    };
+
+    let mut parsed = Args {
+        source,
+        cols: 9,
+        rows: 6,
+        square: 0.025,
+        frames: 20,
+        out: PathBuf::from("../data/calibration.json"),
+    };
+
+    for pair in args[1..].chunks(2) {
+        let [flag, value] = pair else {
+            return Err(invalid());
+        };
+
+        match flag.as_str() {
+            "--cols" => parsed.cols = value.parse().map_err(|_| invalid())?,
+            "--rows" => parsed.rows = value.parse().map_err(|_| invalid())?,
+            "--square" => parsed.square = value.parse().map_err(|_| invalid())?,
+            "--frames" => parsed.frames = value.parse().map_err(|_| invalid())?,
+            "--out" => parsed.out = PathBuf::from(value),
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// `calibrate` subcommand, finds the checkerboard in every image and writes
+/// the solved intrinsics to the calibration file
+pub fn run(args: &[String]) -> Result<(), Error> {
+    let args = parse_args(args)?;
+    let mut size = (0, 0);
+    let mut views = Vec::new();
+
+    let mut add_view = |img: GrayImage, name: &str, views: &mut Vec<Vec<(f64, f64)>>| {
+        size = img.dimensions();
+        match checkerboard::find_corners(&img, args.cols, args.rows) {
+            Some(corners) => {
+                println!("{}: checkerboard found", name);
+                views.push(corners);
+            }
+            None => println!("{}: no checkerboard", name),
+        }
+    };
+
+    match &args.source {
+        Source::Directory(dir) => {
+            let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+                .map_err(|e| Error::CalibrationFailed(format!("{}: {}", dir.display(), e)))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| is_image(path))
+                .collect();
+            paths.sort();
+
+            for path in paths {
+                match image::open(&path) {
+                    Ok(img) => add_view(img.to_luma8(), &path.display().to_string(), &mut views),
+                    Err(e) => eprintln!("{}: {}", path.display(), e),
+                }
+            }
+        }
+        Source::Camera => {
+            // calibrate the camera in the same format the robot streams in
+            let config = yolo::load_model_file()
+                .map(|config| config.camera)
+                .unwrap_or_default();
+            let mut camera = camera::open_camera(&config)?;
+            camera.open_stream()?;
+
+            let mut frame = 0;
+            while views.len() < args.frames {
+                let img = camera.frame()?.decode_image::<RgbFormat>()?;
+                add_view(
+                    DynamicImage::ImageRgb8(img).to_luma8(),
+                    &format!("frame {}", frame),
+                    &mut views,
+                );
+                frame += 1;
+                // give the operator time to move the board between views
+                sleep(Duration::from_millis(1000));
+            }
+        }
+    }
+
+    if views.len() < MIN_VIEWS {
+        return Err(Error::CalibrationFailed(format!(
+            "need at least {} checkerboard views, found {}",
+            MIN_VIEWS,
+            views.len()
+        )));
+    }
+
+    let object: Vec<(f64, f64)> = (0..args.rows)
+        .flat_map(|r| (0..args.cols).map(move |c| (c as f64, r as f64)))
+        .map(|(c, r)| (c * args.square, r * args.square))
+        .collect();
+
+    let (intrinsics, rms_error, used) = calibrate(&object, &views)?;
+
+    println!(
+        "fx {:.2} fy {:.2} cx {:.2} cy {:.2} k1 {:.4} k2 {:.4} p1 {:.4} p2 {:.4}, rms {:.3}px",
+        intrinsics.fx,
+        intrinsics.fy,
+        intrinsics.cx,
+        intrinsics.cy,
+        intrinsics.k1,
+        intrinsics.k2,
+        intrinsics.p1,
+        intrinsics.p2,
+        rms_error
+    );
+
+    let file = CalibrationFile {
+        image_width: size.0,
+        image_height: size.1,
+        rms_error,
+        views: used,
+        intrinsics,
+    };
+    serde_json::to_writer_pretty(File::create(&args.out)?, &file)?;
+
+    println!("Calibration written to {}", args.out.display());
+
+    Ok(())
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ["png", "jpg", "jpeg", "bmp"].contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Zhang's method for the initial guess followed by Levenberg-Marquardt over
+/// the reprojection error. A view we can't get a board pose from is skipped.
+/// Returns the intrinsics, the rms error in pixels and how many views were used
+pub fn calibrate(
+    object: &[(f64, f64)],
+    views: &[Vec<(f64, f64)>],
+) -> Result<(CameraIntrinsics, f64, usize), Error> {
+    let (views, homographies): (Vec<_>, Vec<_>) = views
+        .iter()
+        .enumerate()
+        .filter_map(|(n, image)| match homography(object, image) {
+            Some(h) => Some((image, h)),
+            None => {
+                eprintln!("View {}: degenerate checkerboard, skipped", n);
+                None
+            }
+        })
+        .unzip();
+    enough_views(views.len())?;
+
+    let k = initial_intrinsics(&homographies)
+        .ok_or_else(|| Error::CalibrationFailed("views do not constrain the camera".to_string()))?;
+    let k_inv = k
+        .try_inverse()
+        .ok_or_else(|| Error::CalibrationFailed("singular camera matrix".to_string()))?;
+
+    let (views, poses): (Vec<Vec<(f64, f64)>>, Vec<_>) = views
+        .into_iter()
+        .zip(&homographies)
+        .enumerate()
+        .filter_map(|(n, (image, h))| match extrinsics(&k_inv, h) {
+            Some(pose) => Some((image.clone(), pose)),
+            None => {
+                eprintln!("View {}: no board pose, skipped", n);
+                None
+            }
+        })
+        .unzip();
+    enough_views(views.len())?;
+
+    let mut params = DVector::zeros(INTRINSIC_PARAMS + VIEW_PARAMS * views.len());
+    params[0] = k[(0, 0)];
+    params[1] = k[(1, 1)];
+    params[2] = k[(0, 2)];
+    params[3] = k[(1, 2)];
+
+    for (v, (rvec, t)) in poses.iter().enumerate() {
+        params
+            .fixed_rows_mut::<3>(INTRINSIC_PARAMS + v * VIEW_PARAMS)
+            .copy_from(rvec);
+        params
+            .fixed_rows_mut::<3>(INTRINSIC_PARAMS + v * VIEW_PARAMS + 3)
+            .copy_from(t);
+    }
+
+    let params = refine(params, object, &views);
+    let residuals = reprojection(&params, object, &views);
+    let rms = (residuals.norm_squared() / (residuals.len() / 2) as f64).sqrt();
+
+    let intrinsics = CameraIntrinsics {
+        fx: params[0],
+        fy: params[1],
+        cx: params[2],
+        cy: params[3],
+        k1: params[4],
+        k2: params[5],
+        p1: params[6],
+        p2: params[7],
+        k3: 0.0,
+    };
+
+    Ok((intrinsics, rms, views.len()))
+}
+
+fn enough_views(usable: usize) -> Result<(), Error> {
+    if usable < MIN_VIEWS {
+        return Err(Error::CalibrationFailed(format!(
+            "need at least {} usable checkerboard views, {} left",
+            MIN_VIEWS, usable
+        )));
+    }
+
+    Ok(())
+}
+
+// eigenvector of the smallest eigenvalue of a^T a, the least squares null space
+fn null_space(a: &DMatrix<f64>) -> DVector<f64> {
+    let eigen = SymmetricEigen::new(a.transpose() * a);
+    let (smallest, _) =
+        eigen
+            .eigenvalues
+            .iter()
+            .enumerate()
+            .fold(
+                (0, f64::MAX),
+                |best, (i, &e)| if e < best.1 { (i, e) } else { best },
+            );
+
+    eigen.eigenvectors.column(smallest).into_owned()
+}
+
+// hartley normalisation, centroid to the origin and mean distance sqrt(2)
+fn normalisation(points: &[(f64, f64)]) -> Matrix3<f64> {
+    let n = points.len() as f64;
+    let (mx, my) = points
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), p| (sx + p.0 / n, sy + p.1 / n));
+    let mean_dist = points
+        .iter()
+        .map(|p| (p.0 - mx).hypot(p.1 - my))
+        .sum::<f64>()
+        / n;
+    let s = std::f64::consts::SQRT_2 / mean_dist.max(f64::EPSILON);
+
+    Matrix3::new(s, 0.0, -s * mx, 0.0, s, -s * my, 0.0, 0.0, 1.0)
+}
+
+/// Plane to image homography with the normalised DLT
+fn homography(object: &[(f64, f64)], image: &[(f64, f64)]) -> Option<Matrix3<f64>> {
+    let to = normalisation(object);
+    let ti = normalisation(image);
+    let mut a = DMatrix::zeros(2 * object.len(), 9);
+
+    for (n, (o, i)) in object.iter().zip(image).enumerate() {
+        let o = to * Vector3::new(o.0, o.1, 1.0);
+        let i = ti * Vector3::new(i.0, i.1, 1.0);
+        let (x, y, u, v) = (o.x, o.y, i.x, i.y);
+
+        a.row_mut(2 * n)
+            .copy_from_slice(&[-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u]);
+        a.row_mut(2 * n + 1)
+            .copy_from_slice(&[0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v]);
+    }
+
+    let h = null_space(&a);
+    let h = Matrix3::from_row_slice(h.as_slice());
+
+    // h has unit norm here, collinear corners make it close to rank deficient
+    if h.iter().any(|x| !x.is_finite()) || h.determinant().abs() < 1e-9 {
+        return None;
+    }
+
+    Some(ti.try_inverse()? * h * to)
+}
+
+/// Closed form camera matrix from three or more homographies
+fn initial_intrinsics(homographies: &[Matrix3<f64>]) -> Option<Matrix3<f64>> {
+    let v = |h: &Matrix3<f64>, i: usize, j: usize| {
+        let (hi, hj) = (h.column(i), h.column(j));
+        [
+            hi[0] * hj[0],
+            hi[0] * hj[1] + hi[1] * hj[0],
+            hi[1] * hj[1],
+            hi[2] * hj[0] + hi[0] * hj[2],
+            hi[2] * hj[1] + hi[1] * hj[2],
+            hi[2] * hj[2],
+        ]
+    };
+
+    let mut a = DMatrix::zeros(2 * homographies.len(), 6);
+    for (n, h) in homographies.iter().enumerate() {
+        let v12 = v(h, 0, 1);
+        let v11 = v(h, 0, 0);
+        let v22 = v(h, 1, 1);
+        let diff: Vec<f64> = v11.iter().zip(v22).map(|(a, b)| a - b).collect();
+
+        a.row_mut(2 * n).copy_from_slice(&v12);
+        a.row_mut(2 * n + 1).copy_from_slice(&diff);
+    }
+
+    let mut b = null_space(&a);
+    // b is only known up to scale, B11 has to be positive
+    if b[0] < 0.0 {
+        b = -b;
+    }
+    let (b11, b12, b22, b13, b23, b33) = (b[0], b[1], b[2], b[3], b[4], b[5]);
+
+    let denom = b11 * b22 - b12 * b12;
+    if denom.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let v0 = (b12 * b13 - b11 * b23) / denom;
+    let lambda = b33 - (b13 * b13 + v0 * (b12 * b13 - b11 * b23)) / b11;
+    let alpha = (lambda / b11).sqrt();
+    let beta = (lambda * b11 / denom).sqrt();
+    let gamma = -b12 * alpha * alpha * beta / lambda;
+    let u0 = gamma * v0 / beta - b13 * alpha * alpha / lambda;
+
+    if !(alpha.is_finite() && beta.is_finite() && u0.is_finite() && v0.is_finite()) {
+        return None;
+    }
+
+    // skew is assumed to be zero
+    Some(Matrix3::new(alpha, 0.0, u0, 0.0, beta, v0, 0.0, 0.0, 1.0))
+}
+
+/// Pose of the board in one view, as a rotation vector and translation. None
+/// if the homography doesn't describe a plane in front of the camera
+fn extrinsics(k_inv: &Matrix3<f64>, h: &Matrix3<f64>) -> Option<(Vector3<f64>, Vector3<f64>)> {
+    let mut r1 = k_inv * h.column(0);
+    let scale = 1.0 / r1.norm();
+    if !scale.is_finite() {
+        return None;
+    }
+    r1 *= scale;
+    let mut r2 = k_inv * h.column(1) * scale;
+    let mut t = k_inv * h.column(2) * scale;
+
+    // the board is always in front of the camera
+    if t.z < 0.0 {
+        r1 = -r1;
+        r2 = -r2;
+        t = -t;
+    }
+
+    let r3 = r1.cross(&r2);
+    let r = Matrix3::from_columns(&[r1, r2, r3]);
+    let rotation = Rotation3::from_matrix(&r);
+    let rvec = rotation.scaled_axis();
+
+    let finite = rvec.iter().chain(t.iter()).all(|x| x.is_finite());
+    (finite && t.z > 0.0).then_some((rvec, t))
+}
+
+/// Reprojection residuals (projected - observed) of every corner in every view
+fn reprojection(
+    params: &DVector<f64>,
+    object: &[(f64, f64)],
+    views: &[Vec<(f64, f64)>],
+) -> DVector<f64> {
+    let mut residuals = DVector::zeros(2 * object.len() * views.len());
+
+    for (v, corners) in views.iter().enumerate() {
+        let view = view_residuals(params, v, object, corners);
+        residuals
+            .rows_mut(2 * object.len() * v, view.len())
+            .copy_from(&view);
+    }
+
+    residuals
+}
+
+fn view_residuals(
+    params: &DVector<f64>,
+    view: usize,
+    object: &[(f64, f64)],
+    image: &[(f64, f64)],
+) -> DVector<f64> {
+    let intrinsics = CameraIntrinsics {
+        fx: params[0],
+        fy: params[1],
+        cx: params[2],
+        cy: params[3],
+        k1: params[4],
+        k2: params[5],
+        p1: params[6],
+        p2: params[7],
+        k3: 0.0,
+    };
+    let offset = INTRINSIC_PARAMS + view * VIEW_PARAMS;
+    let rotation = Rotation3::new(params.fixed_rows::<3>(offset).into_owned());
+    let t = params.fixed_rows::<3>(offset + 3).into_owned();
+
+    let mut residuals = DVector::zeros(2 * object.len());
+    for (n, (o, i)) in object.iter().zip(image).enumerate() {
+        let p = rotation * Vector3::new(o.0, o.1, 0.0) + t;
+        let (u, v) = intrinsics.distort(p.x / p.z, p.y / p.z);
+        residuals[2 * n] = u - i.0;
+        residuals[2 * n + 1] = v - i.1;
+    }
+
+    residuals
+}
+
+/// Levenberg-Marquardt with a forward difference jacobian, the view
+/// parameters only touch their own residuals so those columns are cheap
+fn refine(
+    mut params: DVector<f64>,
+    object: &[(f64, f64)],
+    views: &[Vec<(f64, f64)>],
+) -> DVector<f64> {
+    let per_view = 2 * object.len();
+    let mut lambda = 1e-3;
+    let mut residuals = reprojection(&params, object, views);
+    let mut cost = residuals.norm_squared();
+
+    for _ in 0..50 {
+        let mut jacobian = DMatrix::zeros(residuals.len(), params.len());
+
+        for p in 0..params.len() {
+            let eps = 1e-6 * params[p].abs().max(1.0);
+            let mut shifted = params.clone();
+            shifted[p] += eps;
+
+            if p < INTRINSIC_PARAMS {
+                let column = (reprojection(&shifted, object, views) - &residuals) / eps;
+                jacobian.set_column(p, &column);
+            } else {
+                let v = (p - INTRINSIC_PARAMS) / VIEW_PARAMS;
+                let view = view_residuals(&shifted, v, object, &views[v]);
+                let column = (view - residuals.rows(v * per_view, per_view)) / eps;
+                jacobian
+                    .view_mut((v * per_view, p), (per_view, 1))
+                    .copy_from(&column);
+            }
+        }
+
+        let jtj = jacobian.transpose() * &jacobian;
+        let gradient = jacobian.transpose() * &residuals;
+        let mut improved = false;
+
+        // raise the damping until a step lowers the cost
+        while lambda < 1e10 {
+            let mut damped = jtj.clone();
+            for d in 0..damped.nrows() {
+                damped[(d, d)] += lambda * jtj[(d, d)].max(1e-9);
+            }
+
+            let Some(step) = damped.cholesky().map(|c| c.solve(&-&gradient)) else {
+                lambda *= 10.0;
+                continue;
+            };
+
+            let candidate = &params + &step;
+            let candidate_residuals = reprojection(&candidate, object, views);
+            let candidate_cost = candidate_residuals.norm_squared();
+
+            if candidate_cost < cost {
+                let relative = (cost - candidate_cost) / cost;
+                params = candidate;
+                residuals = candidate_residuals;
+                cost = candidate_cost;
+                lambda = (lambda / 10.0).max(1e-12);
+                improved = relative > 1e-9;
+                break;
+            }
+
+            lambda *= 10.0;
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> CameraIntrinsics {
+        CameraIntrinsics {
+            fx: 520.0,
+            fy: 510.0,
+            cx: 318.0,
+            cy: 244.0,
+            k1: -0.12,
+            k2: 0.03,
+            k3: 0.0,
+            p1: 0.001,
+            p2: -0.0008,
+        }
+    }
+
+    // 9x6 inner corners, 25mm squares
+    fn board() -> Vec<(f64, f64)> {
+        (0..6)
+            .flat_map(|r| (0..9).map(move |c| (c as f64 * 0.025, r as f64 * 0.025)))
+            .collect()
+    }
+
+    fn project(
+        intrinsics: &CameraIntrinsics,
+        object: &[(f64, f64)],
+        rvec: [f64; 3],
+        t: [f64; 3],
+    ) -> Vec<(f64, f64)> {
+        let rotation = Rotation3::new(Vector3::from(rvec));
+        let t = Vector3::from(t);
+
+        object
+            .iter()
+            .map(|o| {
+                let p = rotation * Vector3::new(o.0, o.1, 0.0) + t;
+                intrinsics.distort(p.x / p.z, p.y / p.z)
+            })
+            .collect()
+    }
+
+    // the board tilted a different way in every view
+    fn views(intrinsics: &CameraIntrinsics, object: &[(f64, f64)]) -> Vec<Vec<(f64, f64)>> {
+        [
+            ([0.1, -0.2, 0.05], [-0.1, -0.06, 0.5]),
+            ([-0.3, 0.1, 0.0], [-0.12, -0.05, 0.55]),
+            ([0.25, 0.3, -0.1], [-0.08, -0.08, 0.6]),
+            ([-0.1, -0.35, 0.2], [-0.1, -0.04, 0.45]),
+            ([0.35, -0.05, -0.15], [-0.06, -0.07, 0.5]),
+            ([-0.2, 0.25, 0.1], [-0.11, -0.06, 0.65]),
+        ]
+        .into_iter()
+        .map(|(rvec, t)| project(intrinsics, object, rvec, t))
+        .collect()
+    }
+
+    fn assert_recovered(solved: &CameraIntrinsics, truth: &CameraIntrinsics) {
+        assert!((solved.fx - truth.fx).abs() < 0.5, "fx {}", solved.fx);
+        assert!((solved.fy - truth.fy).abs() < 0.5, "fy {}", solved.fy);
+        assert!((solved.cx - truth.cx).abs() < 0.5, "cx {}", solved.cx);
+        assert!((solved.cy - truth.cy).abs() < 0.5, "cy {}", solved.cy);
+        assert!((solved.k1 - truth.k1).abs() < 1e-3, "k1 {}", solved.k1);
+        assert!((solved.k2 - truth.k2).abs() < 1e-2, "k2 {}", solved.k2);
+        assert!((solved.p1 - truth.p1).abs() < 1e-4, "p1 {}", solved.p1);
+        assert!((solved.p2 - truth.p2).abs() < 1e-4, "p2 {}", solved.p2);
+    }
+
+    #[test]
+    fn recovers_synthetic_camera() {
+        let truth = camera();
+        let object = board();
+
+        let (solved, rms, used) = calibrate(&object, &views(&truth, &object)).unwrap();
+
+        assert_eq!(used, 6);
+        assert!(rms < 1e-3, "rms {}", rms);
+        assert_recovered(&solved, &truth);
+    }
+
+    #[test]
+    fn skips_degenerate_view() {
+        let truth = camera();
+        let object = board();
+        let mut views = views(&truth, &object);
+        // every corner on one line, no plane pose explains that
+        views.push(
+            (0..object.len())
+                .map(|n| (100.0 + n as f64, 200.0))
+                .collect(),
+        );
+
+        let (solved, _, used) = calibrate(&object, &views).unwrap();
+
+        assert_eq!(used, 6);
+        assert_recovered(&solved, &truth);
+    }
+
+    #[test]
+    fn fails_with_too_few_views() {
+        let truth = camera();
+        let object = board();
+        let views: Vec<_> = views(&truth, &object).into_iter().take(2).collect();
+
+        assert!(calibrate(&object, &views).is_err());
+    }
+
+    #[test]
+    fn undistort_inverts_distort() {
+        let intrinsics = camera();
+
+        for (x, y) in [(0.0, 0.0), (0.2, -0.1), (-0.3, 0.25)] {
+            let (u, v) = intrinsics.distort(x, y);
+            let (ux, uy) = intrinsics.undistort(u, v);
+            assert!((ux - (intrinsics.fx * x + intrinsics.cx)).abs() < 1e-6);
+            assert!((uy - (intrinsics.fy * y + intrinsics.cy)).abs() < 1e-6);
+        }
+    }
+}
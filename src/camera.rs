@@ -1,4 +1,4 @@
-use image::DynamicImage;
+use async_cell::sync::AsyncCell;
 use nokhwa::{
     Camera,
     pixel_format::RgbFormat,
@@ -7,48 +7,364 @@ use nokhwa::{
         Resolution,
     },
 };
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Sender;
 
+use std::{fs::File, path::Path, sync::Arc, time::Instant};
+
 use crate::{
-    XyXy,
+    annotate::Overlay,
+    calibration::CalibrationFile,
+    detector::Detector,
+    documenter,
     error::Error,
+    pipeline::{self, CapturedFrame, FrameInfo, RateCounter},
+    preview::Preview,
+    reload::{ReloadTrigger, Reloader},
+    tags::{TagDetection, TagDetector},
+    tracker::{Track, Tracker},
     yolo::{self, ModelConfig},
 };
 
-pub fn cam_plus_yolo_detect(yolo_tx: Sender<XyXy>, model_config: ModelConfig) -> Result<(), Error> {
-    let mut model = yolo::load_model(model_config)?.model;
+/// Pinhole intrinsics of the camera in pixels, with brown-conrady distortion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraIntrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    /// radial distortion
+    pub k1: f64,
+    pub k2: f64,
+    pub k3: f64,
+    /// tangential distortion
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl Default for CameraIntrinsics {
+    fn default() -> Self {
+        // uncalibrated 640x480 camera with a 62.2 degree horizontal fov
+        let focal = 320.0 / 31.1_f64.to_radians().tan();
+
+        Self {
+            fx: focal,
+            fy: focal,
+            cx: 320.0,
+            cy: 240.0,
+            k1: 0.0,
+            k2: 0.0,
+            k3: 0.0,
+            p1: 0.0,
+            p2: 0.0,
+        }
+    }
+}
+
+impl CameraIntrinsics {
+    /// Project a normalised image point (x/z, y/z) to distorted pixels
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        let xd = x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+        let yd = y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+
+        (self.fx * xd + self.cx, self.fy * yd + self.cy)
+    }
+
+    /// Pixel as an ideal pinhole camera would have seen it, the inverse of
+    /// `distort` found by fixed point iteration
+    pub fn undistort(&self, u: f64, v: f64) -> (f64, f64) {
+        let xd = (u - self.cx) / self.fx;
+        let yd = (v - self.cy) / self.fy;
+        let (mut x, mut y) = (xd, yd);
+
+        for _ in 0..10 {
+            let r2 = x * x + y * y;
+            let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let dx = 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+            let dy = self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+            x = (xd - dx) / radial;
+            y = (yd - dy) / radial;
+        }
+
+        (self.fx * x + self.cx, self.fy * y + self.cy)
+    }
+}
+
+/// Intrinsics from a file written by the `calibrate` subcommand, scaled to the
+/// resolution the camera streams at. A different aspect ratio means the sensor
+/// is cropped, which scaling can't undo
+pub fn load_calibration(
+    path: impl AsRef<Path>,
+    width: u32,
+    height: u32,
+) -> Result<CameraIntrinsics, Error> {
+    let file: CalibrationFile = serde_json::from_reader(File::open(path)?)?;
+    let (from_w, from_h) = (f64::from(file.image_width), f64::from(file.image_height));
+    let (to_w, to_h) = (f64::from(width), f64::from(height));
+
+    if from_w <= 0.0 || from_h <= 0.0 || ((from_w / from_h) - (to_w / to_h)).abs() > 0.01 {
+        return Err(Error::CalibrationFailed(format!(
+            "calibrated at {}x{}, which does not scale to the {}x{} stream",
+            file.image_width, file.image_height, width, height
+        )));
+    }
+
+    let (sx, sy) = (to_w / from_w, to_h / from_h);
+    if (sx, sy) != (1.0, 1.0) {
+        println!(
+            "Scaling the {}x{} calibration to {}x{}",
+            file.image_width, file.image_height, width, height
+        );
+    }
+
+    Ok(CameraIntrinsics {
+        fx: file.intrinsics.fx * sx,
+        fy: file.intrinsics.fy * sy,
+        cx: file.intrinsics.cx * sx,
+        cy: file.intrinsics.cy * sy,
+        ..file.intrinsics
+    })
+}
+
+/// Pixel formats a V4L2 camera can stream in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelFormat {
+    Mjpeg,
+    Yuyv,
+    Nv12,
+    Gray,
+    Rawrgb,
+}
+
+impl From<PixelFormat> for FrameFormat {
+    fn from(format: PixelFormat) -> Self {
+        match format {
+            PixelFormat::Mjpeg => FrameFormat::MJPEG,
+            PixelFormat::Yuyv => FrameFormat::YUYV,
+            PixelFormat::Nv12 => FrameFormat::NV12,
+            PixelFormat::Gray => FrameFormat::GRAY,
+            PixelFormat::Rawrgb => FrameFormat::RAWRGB,
+        }
+    }
+}
+
+/// Camera section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    /// V4L2 device index, 0 is /dev/video0
+    pub index: u32,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub fps: u32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            index: 0,
+            width: 640,
+            height: 480,
+            format: PixelFormat::Mjpeg,
+            fps: 30,
+        }
+    }
+}
+
+/// Open the V4L2 camera in the configured format, or the closest one the
+/// device supports at the configured resolution, since the intrinsics are for it
+pub fn open_camera(config: &CameraConfig) -> Result<Camera, Error> {
+    let wanted = CameraFormat::new(
+        Resolution::new(config.width, config.height),
+        config.format.into(),
+        config.fps,
+    );
+
+    // open with whatever the device defaults to so it can be probed
+    let any = RequestedFormat::new::<RgbFormat>(RequestedFormatType::None);
+    let mut camera = Camera::with_backend(
+        CameraIndex::Index(config.index),
+        any,
+        ApiBackend::Video4Linux,
+    )?;
+
+    let supported = camera.compatible_camera_formats().unwrap_or_default();
+    println!("Camera {} supports:", config.index);
+    for format in &supported {
+        println!("    {}", describe(format));
+    }
+
+    let chosen = closest_format(&wanted, &supported).unwrap_or(wanted);
+    if chosen != wanted {
+        println!(
+            "Camera does not support {}, falling back to {}",
+            describe(&wanted),
+            describe(&chosen)
+        );
+    }
+
+    let exact = RequestedFormat::new::<RgbFormat>(RequestedFormatType::Exact(chosen));
+    camera.set_camera_requset(exact)?;
+    let streaming = camera.camera_format();
+    println!("Camera streaming {}", describe(&streaming));
+
+    if streaming.resolution() != wanted.resolution() {
+        return Err(anyhow::anyhow!(
+            "Camera streams {}x{} but the intrinsics are for {}x{}, configure a supported resolution",
+            streaming.width(),
+            streaming.height(),
+            config.width,
+            config.height
+        )
+        .into());
+    }
+
+    Ok(camera)
+}
 
-    let res = Resolution {
-        width_x: 640,
-        height_y: 480,
+fn describe(format: &CameraFormat) -> String {
+    format!(
+        "{}x{} {:?} at {}fps",
+        format.width(),
+        format.height(),
+        format.format(),
+        format.frame_rate()
+    )
+}
+
+/// Supported format closest to the wanted one. Resolution matters most since
+/// the intrinsics depend on it, then frame rate, then the pixel format
+fn closest_format(wanted: &CameraFormat, supported: &[CameraFormat]) -> Option<CameraFormat> {
+    let cost = |format: &CameraFormat| {
+        let pixels = |f: &CameraFormat| f64::from(f.width()) * f64::from(f.height());
+        let resolution = (pixels(format) / pixels(wanted)).ln().abs();
+        let fps = (f64::from(format.frame_rate()) - f64::from(wanted.frame_rate())).abs() / 30.0;
+        let pixel_format = if format.format() == wanted.format() {
+            0.0
+        } else {
+            0.1
+        };
+
+        10.0 * resolution + fps + pixel_format
     };
 
-    let frame_format = FrameFormat::MJPEG;
-    let fps = 30;
-    let req_format_type = RequestedFormatType::Exact(CameraFormat::new(res, frame_format, fps));
-    let format = RequestedFormat::new::<RgbFormat>(req_format_type);
+    supported
+        .iter()
+        .copied()
+        .min_by(|a, b| cost(a).total_cmp(&cost(b)))
+}
 
-    let mut camera: Camera =
-        Camera::with_backend(CameraIndex::Index(0), format, ApiBackend::Video4Linux)?;
+pub fn cam_plus_yolo_detect(
+    tracks_cell: Arc<AsyncCell<Vec<Track>>>,
+    tag_tx: Sender<TagDetection>,
+    model_config: ModelConfig,
+    reload: ReloadTrigger,
+) -> Result<(), Error> {
+    let mut tracker = Tracker::new(&model_config.tracker);
+    let mut tag_detector = if model_config.tags.enabled {
+        Some(TagDetector::new(
+            &model_config.tags,
+            &model_config.intrinsics,
+            &model_config.ranging,
+        )?)
+    } else {
+        None
+    };
+    let mut preview = if model_config.preview.enabled {
+        Some(Preview::new(&model_config.preview)?)
+    } else {
+        None
+    };
+    // where a box has to be for nav to drive straight at it
+    let tolerance = model_config.intrinsics.fx * model_config.ranging.bearing_tolerance.tan();
+    let bearing_window = (
+        (model_config.intrinsics.cx - tolerance) as f32,
+        (model_config.intrinsics.cx + tolerance) as f32,
+    );
+    // where nav stops turning towards a box
+    let servo_window = model_config
+        .servo
+        .centered_window(&model_config.intrinsics, model_config.camera.width);
+    let mut detector = Detector::new(&model_config)?;
+    let mut reloader = Reloader::new(&model_config, reload);
+    // capture keeps running while we infer, we only ever see its newest frame
+    let frames = pipeline::spawn_capture(&model_config.source, &model_config.camera)?;
+    let mut inference_rate = RateCounter::default();
+    let mut last_report = Instant::now();
 
-    camera.open_stream()?;
+    while let Some(CapturedFrame {
+        image: img,
+        info: mut frame,
+    }) = frames.take()
+    {
+        // a new model loads in the background, we only swap it in between frames
+        if let Some(reloaded) = reloader.poll() {
+            detector = reloaded;
+        }
 
-    loop {
-        let buffer = camera
-            .frame()
-            .and_then(|buffer| buffer.decode_image::<RgbFormat>());
+        let started = Instant::now();
+        let mut tags = match tag_detector.as_mut() {
+            Some(tag_detector) => tag_detector.detect(&img.to_luma8(), frame),
+            None => Vec::new(),
+        };
+        for tag in &mut tags {
+            tag.frame.inference = started.elapsed();
+            // nav may be busy driving, drop tags rather than stall vision on a full channel
+            let _ = tag_tx.try_send(tag.clone());
+        }
 
-        if let Ok(img) = buffer {
-            if let Some(bbox) = yolo::detect(&mut model, &[DynamicImage::ImageRgb8(img)]) {
-                let _ = yolo_tx.blocking_send(bbox);
-            }
+        let started = Instant::now();
+        let mut detections = detector.detect(&img, frame);
+        // detectors only know the frame, the time they took is filled in here
+        frame.inference = started.elapsed();
+        for detection in &mut detections {
+            detection.frame = frame;
+        }
+        let tracks = tracker.update(detections.clone());
+
+        // only confirmed tracks reach nav, a single false positive never does.
+        // nav only wants the newest tracks, older ones are overwritten
+        if !tracks.is_empty() {
+            tracks_cell.set(tracks.clone());
+        }
+
+        let done = Instant::now();
+        let inference_fps = inference_rate.tick(done);
+        let latency_ms = (done - frame.captured).as_secs_f64() * 1000.0;
+        documenter::push_inference(inference_fps, frames.dropped(), latency_ms);
+
+        if last_report.elapsed().as_secs_f64() >= model_config.vision.report_interval_secs {
+            last_report = done;
+            println!(
+                "Vision at {:.1} fps, {} frames dropped, {:.0}ms capture to detection",
+                inference_fps,
+                frames.dropped(),
+                latency_ms
+            );
+        }
+
+        if let Some(preview) = preview.as_mut() {
+            let overlay = Overlay {
+                detections: &detections,
+                tracks: &tracks,
+                tags: &tags,
+                windows: &[servo_window, bearing_window],
+            };
+            preview.publish(&img, &overlay);
         }
     }
+
+    Ok(())
 }
 
 #[allow(dead_code)]
 pub async fn yolo_detect_test(model_config: ModelConfig) -> Option<()> {
-    let mut model = yolo::load_model(model_config).ok()?.model;
+    let mut model = yolo::load_model(model_config).ok()?;
 
     // load the yolo model
     let img_path = "../data/IMG_8405.JPG"; // change the path if needed
@@ -57,7 +373,9 @@ pub async fn yolo_detect_test(model_config: ModelConfig) -> Option<()> {
         .decode()
         .unwrap();
 
-    println!("yolo detect test {:?}", yolo::detect(&mut model, &[img])?);
+    let frame = FrameInfo::new(0, &img);
+    let detections = yolo::detect(&mut model, &img, frame);
+    println!("yolo detect test {:?}", detections.first()?);
 
     Some(())
 }
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, VecDeque};
+
+use image::GrayImage;
+
+// pixels ignored around the image border
+const BORDER: usize = 4;
+// radius of the non maximum suppression window
+const NMS_RADIUS: usize = 4;
+// a grid neighbour has to be within this fraction of the lattice step
+const MATCH_RADIUS: f64 = 0.35;
+// how many of the strongest corners we try to grow a grid from
+const SEEDS: usize = 10;
+// radius of the ring sampled to tell X junctions from board edge T junctions
+const RING_RADIUS: f64 = 4.0;
+const RING_SAMPLES: usize = 16;
+
+/// Find the inner corners of a `cols` x `rows` checkerboard, returned row by
+/// row. None if the full board is not visible
+pub fn find_corners(img: &GrayImage, cols: usize, rows: usize) -> Option<Vec<(f64, f64)>> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    if width <= 2 * BORDER || height <= 2 * BORDER {
+        return None;
+    }
+
+    let pixels: Vec<f32> = img.pixels().map(|p| p.0[0] as f32).collect();
+    let blurred = box_blur(&box_blur(&pixels, width, height), width, height);
+    let candidates = saddle_points(&blurred, width, height, 3 * cols * rows + 20);
+
+    if candidates.len() < cols * rows {
+        return None;
+    }
+
+    (0..SEEDS.min(candidates.len())).find_map(|seed| grow_grid(&candidates, seed, cols, rows))
+}
+
+fn box_blur(src: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut dst = src.to_vec();
+
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let mut sum = 0.0;
+            for dy in 0..3 {
+                for dx in 0..3 {
+                    sum += src[(y + dy - 1) * width + x + dx - 1];
+                }
+            }
+            dst[y * width + x] = sum / 9.0;
+        }
+    }
+
+    dst
+}
+
+/// X shaped corners of a checkerboard are saddle points of the intensity,
+/// where the hessian determinant is strongly negative
+fn saddle_points(img: &[f32], width: usize, height: usize, limit: usize) -> Vec<(f64, f64)> {
+    let at = |x: usize, y: usize| img[y * width + x];
+    let mut response = vec![0.0_f32; width * height];
+    let mut derivatives = vec![[0.0_f32; 5]; width * height];
+
+    for y in BORDER..height - BORDER {
+        for x in BORDER..width - BORDER {
+            let gx = (at(x + 1, y) - at(x - 1, y)) / 2.0;
+            let gy = (at(x, y + 1) - at(x, y - 1)) / 2.0;
+            let ixx = at(x + 1, y) - 2.0 * at(x, y) + at(x - 1, y);
+            let iyy = at(x, y + 1) - 2.0 * at(x, y) + at(x, y - 1);
+            let ixy =
+                (at(x + 1, y + 1) - at(x + 1, y - 1) - at(x - 1, y + 1) + at(x - 1, y - 1)) / 4.0;
+
+            response[y * width + x] = ixy * ixy - ixx * iyy;
+            derivatives[y * width + x] = [gx, gy, ixx, iyy, ixy];
+        }
+    }
+
+    let max = response.iter().copied().fold(0.0, f32::max);
+    if max <= 0.0 {
+        return Vec::new();
+    }
+
+    let threshold = 0.05 * max;
+    let mut peaks = Vec::new();
+
+    for y in BORDER..height - BORDER {
+        for x in BORDER..width - BORDER {
+            let r = response[y * width + x];
+            if r < threshold {
+                continue;
+            }
+
+            let is_max = (y.saturating_sub(NMS_RADIUS)..(y + NMS_RADIUS + 1).min(height))
+                .flat_map(|ny| {
+                    (x.saturating_sub(NMS_RADIUS)..(x + NMS_RADIUS + 1).min(width))
+                        .map(move |nx| (nx, ny))
+                })
+                .all(|(nx, ny)| response[ny * width + nx] <= r);
+
+            if is_max && is_x_junction(img, width, x, y) {
+                peaks.push((r, x, y));
+            }
+        }
+    }
+
+    peaks.sort_by(|a, b| b.0.total_cmp(&a.0));
+    peaks.truncate(limit);
+
+    peaks
+        .into_iter()
+        .map(|(_, x, y)| {
+            // one newton step on the intensity gives the sub pixel saddle
+            let [gx, gy, ixx, iyy, ixy] = derivatives[y * width + x];
+            let det = ixx * iyy - ixy * ixy;
+            let dx = -(iyy * gx - ixy * gy) / det;
+            let dy = -(ixx * gy - ixy * gx) / det;
+
+            if dx.abs() <= 1.0 && dy.abs() <= 1.0 {
+                (x as f64 + dx as f64, y as f64 + dy as f64)
+            } else {
+                (x as f64, y as f64)
+            }
+        })
+        .collect()
+}
+
+/// Walking around an inner corner the intensity goes dark, bright, dark,
+/// bright. Where the board meets the background it only changes twice
+fn is_x_junction(img: &[f32], width: usize, x: usize, y: usize) -> bool {
+    let ring: Vec<f32> = (0..RING_SAMPLES)
+        .map(|n| {
+            let angle = n as f64 * std::f64::consts::TAU / RING_SAMPLES as f64;
+            let sx = (x as f64 + RING_RADIUS * angle.cos()).round() as usize;
+            let sy = (y as f64 + RING_RADIUS * angle.sin()).round() as usize;
+            img[sy * width + sx]
+        })
+        .collect();
+    let mean = ring.iter().sum::<f32>() / ring.len() as f32;
+
+    let changes = (0..ring.len())
+        .filter(|&n| (ring[n] > mean) != (ring[(n + 1) % ring.len()] > mean))
+        .count();
+
+    changes == 4
+}
+
+fn sub(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn norm(v: (f64, f64)) -> f64 {
+    v.0.hypot(v.1)
+}
+
+/// Grow a lattice out from `seed`, predicting every neighbour from the local
+/// step vectors so perspective distortion is followed
+fn grow_grid(
+    points: &[(f64, f64)],
+    seed: usize,
+    cols: usize,
+    rows: usize,
+) -> Option<Vec<(f64, f64)>> {
+    let origin = points[seed];
+    let mut by_distance: Vec<usize> = (0..points.len()).filter(|&i| i != seed).collect();
+    by_distance
+        .sort_by(|&a, &b| norm(sub(points[a], origin)).total_cmp(&norm(sub(points[b], origin))));
+
+    // two roughly perpendicular steps of similar length span the lattice
+    let step_a = sub(points[*by_distance.first()?], origin);
+    let step_b = by_distance.iter().take(8).skip(1).find_map(|&i| {
+        let v = sub(points[i], origin);
+        let cos = (v.0 * step_a.0 + v.1 * step_a.1) / (norm(v) * norm(step_a));
+        let ratio = norm(v) / norm(step_a);
+        (cos.abs() < 0.5 && (0.5..2.0).contains(&ratio)).then_some(v)
+    })?;
+
+    let max_span = cols.max(rows) as i64;
+    let mut grid: HashMap<(i64, i64), usize> = HashMap::from([((0, 0), seed)]);
+    let mut used = vec![false; points.len()];
+    used[seed] = true;
+    let mut queue = VecDeque::from([(0_i64, 0_i64)]);
+
+    while let Some((i, j)) = queue.pop_front() {
+        let p = points[grid[&(i, j)]];
+
+        // local lattice steps, falling back to the seed steps
+        let local = |di: i64, dj: i64, fallback: (f64, f64)| {
+            if let Some(&n) = grid.get(&(i - di, j - dj)) {
+                sub(p, points[n])
+            } else if let Some(&n) = grid.get(&(i + di, j + dj)) {
+                sub(points[n], p)
+            } else {
+                fallback
+            }
+        };
+        let a = local(1, 0, step_a);
+        let b = local(0, 1, step_b);
+
+        for (di, dj, step) in [(1, 0, a), (-1, 0, a), (0, 1, b), (0, -1, b)] {
+            let cell = (i + di, j + dj);
+            if grid.contains_key(&cell) {
+                continue;
+            }
+
+            // only one of di / dj is set and step points along that axis
+            let sign = (di + dj) as f64;
+            let predicted = (p.0 + sign * step.0, p.1 + sign * step.1);
+
+            let nearest = (0..points.len()).filter(|&n| !used[n]).min_by(|&x, &y| {
+                norm(sub(points[x], predicted)).total_cmp(&norm(sub(points[y], predicted)))
+            });
+
+            if let Some(n) = nearest
+                && norm(sub(points[n], predicted)) < MATCH_RADIUS * norm(step)
+            {
+                used[n] = true;
+                grid.insert(cell, n);
+                queue.push_back(cell);
+            }
+        }
+
+        let (min_i, max_i, min_j, max_j) = bounds(&grid);
+        if max_i - min_i >= max_span + 2 || max_j - min_j >= max_span + 2 {
+            return None;
+        }
+    }
+
+    let (min_i, max_i, min_j, max_j) = bounds(&grid);
+    let (span_i, span_j) = ((max_i - min_i + 1) as usize, (max_j - min_j + 1) as usize);
+    if grid.len() != cols * rows {
+        return None;
+    }
+
+    let corner = |i: i64, j: i64| grid.get(&(i + min_i, j + min_j)).map(|&n| points[n]);
+
+    if (span_i, span_j) == (rows, cols) {
+        (0..rows as i64)
+            .flat_map(|i| (0..cols as i64).map(move |j| (i, j)))
+            .map(|(i, j)| corner(i, j))
+            .collect()
+    } else if (span_i, span_j) == (cols, rows) {
+        (0..rows as i64)
+            .flat_map(|j| (0..cols as i64).map(move |i| (i, j)))
+            .map(|(i, j)| corner(i, j))
+            .collect()
+    } else {
+        None
+    }
+}
+
+fn bounds(grid: &HashMap<(i64, i64), usize>) -> (i64, i64, i64, i64) {
+    grid.keys().fold(
+        (i64::MAX, i64::MIN, i64::MAX, i64::MIN),
+        |(min_i, max_i, min_j, max_j), &(i, j)| {
+            (min_i.min(i), max_i.max(i), min_j.min(j), max_j.max(j))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    const SQUARE: u32 = 20;
+    const ORIGIN: (u32, u32) = (50, 40);
+
+    // a board of (cols + 1) x (rows + 1) squares on a white background
+    fn render(cols: u32, rows: u32) -> GrayImage {
+        GrayImage::from_fn(320, 240, |x, y| {
+            let inside = x >= ORIGIN.0
+                && y >= ORIGIN.1
+                && x < ORIGIN.0 + (cols + 1) * SQUARE
+                && y < ORIGIN.1 + (rows + 1) * SQUARE;
+            let dark =
+                inside && ((x - ORIGIN.0) / SQUARE + (y - ORIGIN.1) / SQUARE).is_multiple_of(2);
+            Luma([if dark { 20 } else { 235 }])
+        })
+    }
+
+    #[test]
+    fn finds_every_inner_corner() {
+        let corners = find_corners(&render(9, 6), 9, 6).unwrap();
+
+        assert_eq!(corners.len(), 54);
+        for r in 1..=6 {
+            for c in 1..=9 {
+                // the corner sits between the last pixel of one square and the first of the next
+                let expected = (
+                    (ORIGIN.0 + c * SQUARE) as f64 - 0.5,
+                    (ORIGIN.1 + r * SQUARE) as f64 - 0.5,
+                );
+                assert!(
+                    corners.iter().any(|&p| norm(sub(p, expected)) < 1.0),
+                    "no corner near {:?}",
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn corners_come_row_by_row() {
+        let corners = find_corners(&render(9, 6), 9, 6).unwrap();
+
+        // neighbours within a row are one square apart, rows start a row lower
+        for row in corners.chunks(9) {
+            for pair in row.windows(2) {
+                assert!((norm(sub(pair[1], pair[0])) - SQUARE as f64).abs() < 1.0);
+            }
+        }
+        let row_step = norm(sub(corners[9], corners[0]));
+        assert!((row_step - SQUARE as f64).abs() < 1.0);
+    }
+
+    #[test]
+    fn rejects_wrong_board_size() {
+        assert!(find_corners(&render(7, 5), 9, 6).is_none());
+    }
+
+    #[test]
+    fn rejects_blank_image() {
+        let blank = GrayImage::from_pixel(320, 240, Luma([128]));
+        assert!(find_corners(&blank, 9, 6).is_none());
+    }
+}
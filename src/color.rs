@@ -0,0 +1,237 @@
+use image::DynamicImage;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+use crate::pipeline::FrameInfo;
+use crate::yolo::Detection;
+
+/// HSV range of one object class. Hue is in degrees, a range with
+/// `hue_min > hue_max` wraps through 0 (red)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColorClass {
+    pub name: String,
+    pub hue_min: f32,
+    pub hue_max: f32,
+    /// saturation and value from 0 to 1
+    pub sat_min: f32,
+    pub val_min: f32,
+}
+
+impl ColorClass {
+    fn new(name: &str, hue_min: f32, hue_max: f32, sat_min: f32, val_min: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            hue_min,
+            hue_max,
+            sat_min,
+            val_min,
+        }
+    }
+
+    fn contains(&self, (h, s, v): (f32, f32, f32)) -> bool {
+        let hue = if self.hue_min <= self.hue_max {
+            (self.hue_min..=self.hue_max).contains(&h)
+        } else {
+            h >= self.hue_min || h <= self.hue_max
+        };
+
+        hue && s >= self.sat_min && v >= self.val_min
+    }
+}
+
+/// Color detector section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ColorConfig {
+    pub classes: Vec<ColorClass>,
+    /// blobs smaller than this many pixels are noise
+    pub min_area: u32,
+    /// blobs filling less of their box than this are not objects
+    pub min_fill: f32,
+    /// only every `step`th pixel in each direction is looked at
+    pub step: u32,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            classes: vec![
+                ColorClass::new("football", 16.0, 40.0, 0.5, 0.4),
+                ColorClass::new("green cone", 80.0, 160.0, 0.4, 0.25),
+                ColorClass::new("purple cone", 260.0, 320.0, 0.3, 0.2),
+                ColorClass::new("red cone", 340.0, 15.0, 0.5, 0.3),
+                ColorClass::new("yellow cone", 45.0, 70.0, 0.5, 0.4),
+            ],
+            min_area: 150,
+            min_fill: 0.3,
+            step: 2,
+        }
+    }
+}
+
+/// Model free detector that segments the frame by color and reports every
+/// large enough blob as a detection
+#[derive(Debug)]
+pub struct ColorDetector {
+    config: ColorConfig,
+    /// class id of every color class, lined up with the yolo class names
+    class_ids: Vec<usize>,
+    /// the yolo class names followed by color classes yolo doesn't know
+    names: Vec<String>,
+}
+
+impl ColorDetector {
+    pub fn new(config: &ColorConfig, class_names: &[String]) -> Self {
+        let mut names = class_names.to_vec();
+        let class_ids = config
+            .classes
+            .iter()
+            .map(
+                |class| match names.iter().position(|name| *name == class.name) {
+                    Some(id) => id,
+                    // ids past the yolo classes never collide with a real one
+                    None => {
+                        println!(
+                            "Color class {} is not in class_names, giving it id {}",
+                            class.name,
+                            names.len()
+                        );
+                        names.push(class.name.clone());
+                        names.len() - 1
+                    }
+                },
+            )
+            .collect();
+
+        Self {
+            config: config.clone(),
+            class_ids,
+            names,
+        }
+    }
+
+    pub fn class_names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+
+    /// Every blob in the frame, sorted by confidence like yolo::detect
+    pub fn detect(&self, img: &DynamicImage, frame: FrameInfo) -> Vec<Detection> {
+        let img = img.to_rgb8();
+        let step = self.config.step.max(1);
+        let (width, height) = (img.width().div_ceil(step), img.height().div_ceil(step));
+
+        let hsv: Vec<(f32, f32, f32)> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| rgb_to_hsv(img.get_pixel(x * step, y * step).0))
+            .collect();
+
+        let mut detections = Vec::new();
+        for (class, &class_id) in self.config.classes.iter().zip(&self.class_ids) {
+            let mask: Vec<bool> = hsv.iter().map(|&pixel| class.contains(pixel)).collect();
+
+            for (area, (x1, y1, x2, y2)) in blobs(&mask, width as usize, height as usize) {
+                let box_area = (x2 - x1 + 1) * (y2 - y1 + 1);
+                let fill = area as f32 / box_area as f32;
+                if (area as u32) * step * step < self.config.min_area || fill < self.config.min_fill
+                {
+                    continue;
+                }
+
+                let scale = |v: usize| (v as u32 * step) as f32;
+                detections.push(Detection {
+                    bbox: (scale(x1), scale(y1), scale(x2 + 1), scale(y2 + 1)),
+                    class_id,
+                    class_name: class.name.clone(),
+                    // a solid blob is more likely an object than a ragged one
+                    confidence: fill.min(1.0),
+                    frame,
+                    mask: None,
+                });
+            }
+        }
+
+        detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+        detections
+    }
+}
+
+/// Hue in degrees, saturation and value from 0 to 1
+fn rgb_to_hsv([r, g, b]: [u8; 3]) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    (hue, saturation, max)
+}
+
+/// 4-connected components of the mask as (pixel count, inclusive bounding box)
+fn blobs(mask: &[bool], width: usize, height: usize) -> Vec<(usize, (usize, usize, usize, usize))> {
+    let mut visited = vec![false; mask.len()];
+    let mut found = Vec::new();
+
+    for start in 0..mask.len() {
+        if !mask[start] || visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        let mut queue = VecDeque::from([start]);
+        let (mut x1, mut y1, mut x2, mut y2) = (width, height, 0, 0);
+        let mut area = 0;
+
+        while let Some(idx) = queue.pop_front() {
+            let (x, y) = (idx % width, idx / width);
+            area += 1;
+            (x1, y1, x2, y2) = (x1.min(x), y1.min(y), x2.max(x), y2.max(y));
+
+            let neighbours = [
+                (x > 0).then(|| idx - 1),
+                (x + 1 < width).then(|| idx + 1),
+                (y > 0).then(|| idx - width),
+                (y + 1 < height).then(|| idx + width),
+            ];
+            for n in neighbours.into_iter().flatten() {
+                if mask[n] && !visited[n] {
+                    visited[n] = true;
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        found.push((area, (x1, y1, x2, y2)));
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_color_classes_get_ids_past_the_yolo_classes() {
+        let class_names: Vec<String> = ["person", "football", "car"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let detector = ColorDetector::new(&ColorConfig::default(), &class_names);
+
+        // football keeps its yolo id, the cones follow the yolo classes
+        assert_eq!(detector.class_ids, vec![1, 3, 4, 5, 6]);
+        assert_eq!(detector.class_names()[..3], class_names[..]);
+        assert_eq!(detector.class_names()[3], "green cone");
+    }
+}
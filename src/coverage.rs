@@ -0,0 +1,288 @@
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::nav::normalize_angle;
+use crate::odom::OdomData;
+use crate::planner::Waypoint;
+
+/// How the bot searches the arena for charms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchStrategy {
+    /// bounce around with random step sizes (the original behaviour)
+    Random,
+    /// back and forth lanes across the arena
+    Boustrophedon,
+    /// rectangular spiral from the arena edge inwards
+    Spiral,
+    /// drive to frontiers of the occupancy grid until the arena is mapped
+    Frontier,
+}
+
+/// Coverage section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CoverageConfig {
+    pub strategy: SearchStrategy,
+    /// arena bounds in the odom frame, meters
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+    /// distance between two lanes / spiral rings
+    pub lane_spacing: f64,
+    /// size of a coverage cell
+    pub cell_size: f64,
+    /// horizontal field of view of the camera in radians
+    pub camera_fov: f64,
+    /// how far away (meters) the camera can still recognise a charm
+    pub view_range: f64,
+}
+
+impl Default for CoverageConfig {
+    fn default() -> Self {
+        Self {
+            strategy: SearchStrategy::Random,
+            min_x: -1.5,
+            min_y: -1.5,
+            max_x: 1.5,
+            max_y: 1.5,
+            lane_spacing: 0.5,
+            cell_size: 0.1,
+            camera_fov: 62.2_f64.to_radians(),
+            view_range: 1.5,
+        }
+    }
+}
+
+impl CoverageConfig {
+    /// The plans step by lane_spacing and the map by cell_size, neither ends
+    /// without a positive step and a non empty arena
+    pub fn validate(&self) -> Result<(), Error> {
+        let positive = |x: f64| x.is_finite() && x > 0.0;
+        if !positive(self.lane_spacing) || !positive(self.cell_size) {
+            return Err(anyhow::anyhow!(
+                "Coverage lane_spacing {} and cell_size {} have to be positive",
+                self.lane_spacing,
+                self.cell_size
+            )
+            .into());
+        }
+
+        if !(self.min_x < self.max_x && self.min_y < self.max_y) {
+            return Err(anyhow::anyhow!(
+                "Coverage bounds ({}, {}) to ({}, {}) are empty",
+                self.min_x,
+                self.min_y,
+                self.max_x,
+                self.max_y
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Ordered list of waypoints that sweeps the whole arena
+#[derive(Debug, Clone)]
+pub struct CoveragePlan {
+    waypoints: Vec<Waypoint>,
+    next: usize,
+}
+
+impl CoveragePlan {
+    pub fn new(config: &CoverageConfig) -> Self {
+        if let Err(e) = config.validate() {
+            eprintln!("No coverage plan: {}", e);
+            return Self {
+                waypoints: Vec::new(),
+                next: 0,
+            };
+        }
+
+        let waypoints = match config.strategy {
+            SearchStrategy::Random | SearchStrategy::Frontier => Vec::new(),
+            SearchStrategy::Boustrophedon => boustrophedon(config),
+            SearchStrategy::Spiral => spiral(config),
+        };
+
+        Self { waypoints, next: 0 }
+    }
+
+    /// waypoint we are currently driving to, None once the plan is done
+    pub fn current(&self) -> Option<Waypoint> {
+        self.waypoints.get(self.next).copied()
+    }
+
+    pub fn advance(&mut self) {
+        self.next += 1;
+    }
+}
+
+fn boustrophedon(config: &CoverageConfig) -> Vec<Waypoint> {
+    let half = config.lane_spacing / 2.0;
+    let (left, right) = (config.min_x + half, config.max_x - half);
+    let mut waypoints = Vec::new();
+    let mut y = config.min_y + half;
+    let mut forward = true;
+
+    while y <= config.max_y - half {
+        let (from, to) = if forward {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        waypoints.push(Waypoint { x: from, y });
+        waypoints.push(Waypoint { x: to, y });
+
+        forward = !forward;
+        y += config.lane_spacing;
+    }
+
+    waypoints
+}
+
+fn spiral(config: &CoverageConfig) -> Vec<Waypoint> {
+    let half = config.lane_spacing / 2.0;
+    let (mut x0, mut y0) = (config.min_x + half, config.min_y + half);
+    let (mut x1, mut y1) = (config.max_x - half, config.max_y - half);
+    let mut waypoints = Vec::new();
+
+    while x0 <= x1 && y0 <= y1 {
+        waypoints.push(Waypoint { x: x0, y: y0 });
+        waypoints.push(Waypoint { x: x1, y: y0 });
+        waypoints.push(Waypoint { x: x1, y: y1 });
+        waypoints.push(Waypoint { x: x0, y: y1 });
+        // step in before starting the next ring
+        waypoints.push(Waypoint {
+            x: x0,
+            y: y0 + config.lane_spacing,
+        });
+
+        x0 += config.lane_spacing;
+        y0 += config.lane_spacing;
+        x1 -= config.lane_spacing;
+        y1 -= config.lane_spacing;
+    }
+
+    waypoints
+}
+
+/// Which cells of the arena the camera has already looked at
+#[derive(Debug, Clone)]
+pub struct CoverageMap {
+    config: CoverageConfig,
+    width: usize,
+    height: usize,
+    seen: Vec<bool>,
+    seen_count: usize,
+}
+
+impl CoverageMap {
+    pub fn new(config: &CoverageConfig) -> Self {
+        let width = ((config.max_x - config.min_x) / config.cell_size)
+            .ceil()
+            .max(1.0) as usize;
+        let height = ((config.max_y - config.min_y) / config.cell_size)
+            .ceil()
+            .max(1.0) as usize;
+
+        Self {
+            config: config.clone(),
+            width,
+            height,
+            seen: vec![false; width * height],
+            seen_count: 0,
+        }
+    }
+
+    /// Mark every cell inside the camera field of view as seen
+    pub fn sweep(&mut self, pose: &OdomData) {
+        let yaw = pose.yaw();
+        let half_fov = self.config.camera_fov / 2.0;
+
+        for cy in 0..self.height {
+            for cx in 0..self.width {
+                let idx = cy * self.width + cx;
+                if self.seen[idx] {
+                    continue;
+                }
+
+                let x = self.config.min_x + (cx as f64 + 0.5) * self.config.cell_size;
+                let y = self.config.min_y + (cy as f64 + 0.5) * self.config.cell_size;
+                let dx = x - pose.x1;
+                let dy = y - pose.y1;
+
+                if dx.hypot(dy) > self.config.view_range {
+                    continue;
+                }
+
+                if normalize_angle(dy.atan2(dx) - yaw).abs() <= half_fov {
+                    self.seen[idx] = true;
+                    self.seen_count += 1;
+                }
+            }
+        }
+    }
+
+    /// percentage of the arena the camera has swept so far
+    pub fn percentage(&self) -> f64 {
+        100.0 * self.seen_count as f64 / self.seen.len() as f64
+    }
+
+    pub fn seen_cells(&self) -> usize {
+        self.seen_count
+    }
+
+    pub fn total_cells(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_spacing_gives_an_empty_plan() {
+        for lane_spacing in [0.0, -0.5, f64::NAN] {
+            let config = CoverageConfig {
+                strategy: SearchStrategy::Boustrophedon,
+                lane_spacing,
+                ..CoverageConfig::default()
+            };
+
+            assert!(config.validate().is_err());
+            assert_eq!(CoveragePlan::new(&config).current(), None);
+        }
+    }
+
+    #[test]
+    fn empty_arena_is_rejected() {
+        let config = CoverageConfig {
+            strategy: SearchStrategy::Spiral,
+            min_x: 1.0,
+            max_x: -1.0,
+            ..CoverageConfig::default()
+        };
+
+        assert!(config.validate().is_err());
+        assert_eq!(CoveragePlan::new(&config).current(), None);
+    }
+
+    #[test]
+    fn boustrophedon_alternates_lanes() {
+        let config = CoverageConfig {
+            strategy: SearchStrategy::Boustrophedon,
+            ..CoverageConfig::default()
+        };
+        let plan = boustrophedon(&config);
+
+        // 3m arena in 0.5m lanes, two waypoints per lane
+        assert_eq!(plan.len(), 12);
+        assert_eq!(plan[0], Waypoint { x: -1.25, y: -1.25 });
+        assert_eq!(plan[1], Waypoint { x: 1.25, y: -1.25 });
+        assert_eq!(plan[2], Waypoint { x: 1.25, y: -0.75 });
+    }
+}
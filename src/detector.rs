@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use image::DynamicImage;
+use serde::Deserialize;
+
+use crate::color::ColorDetector;
+use crate::error::Error;
+use crate::pipeline::FrameInfo;
+use crate::yolo::{self, Detection, Model, ModelConfig};
+
+/// Which detector the camera pipeline runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectorKind {
+    /// the ONNX model, falls back to color when the model file is missing
+    #[default]
+    Yolo,
+    /// HSV color segmentation, no model needed and cheap enough for low power runs
+    Color,
+}
+
+/// Either detector behind one interface, both produce yolo::Detection
+#[derive(Debug)]
+pub enum Detector {
+    Yolo(Model),
+    Color(ColorDetector),
+}
+
+impl Detector {
+    pub fn new(config: &ModelConfig) -> Result<Self, Error> {
+        let color = || ColorDetector::new(&config.color, &config.class_names);
+
+        match config.detector {
+            DetectorKind::Yolo if Path::new(&config.model_path).exists() => {
+                Ok(Self::Yolo(yolo::load_model(config.clone())?))
+            }
+            DetectorKind::Yolo => {
+                println!(
+                    "ONNX model {} not found, falling back to the color detector",
+                    config.model_path
+                );
+                Ok(Self::Color(color()))
+            }
+            DetectorKind::Color => Ok(Self::Color(color())),
+        }
+    }
+
+    /// Exactly the configured detector, a missing model is an error instead of
+    /// a fallback. Evaluation uses this so it never measures a stand-in
+    pub fn configured(config: &ModelConfig) -> Result<Self, Error> {
+        match config.detector {
+            DetectorKind::Yolo if !Path::new(&config.model_path).exists() => {
+                Err(anyhow::anyhow!("ONNX model {} not found", config.model_path).into())
+            }
+            DetectorKind::Yolo => Ok(Self::Yolo(yolo::load_model(config.clone())?)),
+            DetectorKind::Color => Ok(Self::Color(ColorDetector::new(
+                &config.color,
+                &config.class_names,
+            ))),
+        }
+    }
+
+    pub fn detect(&mut self, img: &DynamicImage, frame: FrameInfo) -> Vec<Detection> {
+        match self {
+            Self::Yolo(model) => yolo::detect(model, img, frame),
+            Self::Color(detector) => detector.detect(img, frame),
+        }
+    }
+
+    /// class names indexed by class id, may be empty for a yolo model that
+    /// carries its own names
+    pub fn class_names(&self) -> Vec<String> {
+        match self {
+            Self::Yolo(model) => model.class_names.clone(),
+            Self::Color(detector) => detector.class_names(),
+        }
+    }
+}
@@ -1,9 +1,13 @@
 /// Script for compiling together different pieces of data per module.
 /// will be used for putting into mongodb
 use crate::logger::{
-    Battery, ErrorDetails, ErrorSeverity, EventType, LidarData, Location, LogEntry, Sensors, Status,
+    Battery, Coverage, ErrorDetails, ErrorSeverity, EventType, LidarData, Location, LogEntry,
+    Sensors, Status, Swarm, SwarmPeer, Vision,
 };
 
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
 // pub type curDirection = Rc<RefCell<Direction>>;
 
 static mut NANGLE_I: f32 = 1.1;
@@ -13,6 +17,27 @@ static mut NSPEED: u64 = 1234;
 static mut NTT: f64 = 1.2;
 static mut NDIST: f64 = 1.2;
 
+// operation and message of the entry, strings can't live in a static mut
+static NOPERATION: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+// written from the nav task
+static NCOVERAGE: AtomicU64 = AtomicU64::new(0);
+static NSEEN: AtomicUsize = AtomicUsize::new(0);
+static NCELLS: AtomicUsize = AtomicUsize::new(0);
+
+// written from the capture and inference threads, floats are stored as their bits
+static NCAPTURE_FPS: AtomicU64 = AtomicU64::new(0);
+static NINFERENCE_FPS: AtomicU64 = AtomicU64::new(0);
+static NDROPPED: AtomicU64 = AtomicU64::new(0);
+static NINFERENCE_MS: AtomicU64 = AtomicU64::new(0);
+static NDECISION_MS: AtomicU64 = AtomicU64::new(0);
+
+// written from the swarm receive task
+static NSWARM_ACCEPTED: AtomicU64 = AtomicU64::new(0);
+static NSWARM_REJECTED: AtomicU64 = AtomicU64::new(0);
+static NSWARM_UNKNOWN: AtomicU64 = AtomicU64::new(0);
+static NSWARM_PEERS: Mutex<Vec<SwarmPeer>> = Mutex::new(Vec::new());
+
 pub async fn generate_log_entry() -> LogEntry {
     // println!("Generating log entry");
 
@@ -31,6 +56,31 @@ pub async fn generate_log_entry() -> LogEntry {
         }
     };
 
+    let coverage = Coverage {
+        percentage: load_f64(&NCOVERAGE),
+        seen_cells: NSEEN.load(Ordering::Relaxed),
+        total_cells: NCELLS.load(Ordering::Relaxed),
+    };
+
+    let vision = Vision {
+        capture_fps: load_f64(&NCAPTURE_FPS),
+        inference_fps: load_f64(&NINFERENCE_FPS),
+        dropped_frames: NDROPPED.load(Ordering::Relaxed),
+        inference_latency_ms: load_f64(&NINFERENCE_MS),
+        decision_latency_ms: load_f64(&NDECISION_MS),
+    };
+
+    let peers = NSWARM_PEERS
+        .lock()
+        .map(|peers| peers.clone())
+        .unwrap_or_default();
+    let swarm = Swarm {
+        accepted_messages: NSWARM_ACCEPTED.load(Ordering::Relaxed),
+        rejected_messages: NSWARM_REJECTED.load(Ordering::Relaxed),
+        unknown_senders: NSWARM_UNKNOWN.load(Ordering::Relaxed),
+        peers,
+    };
+
     let battery = Battery {
         level: 85.0,
         voltage: 12.6,
@@ -46,15 +96,29 @@ pub async fn generate_log_entry() -> LogEntry {
         severity: ErrorSeverity::Low,
     };
 
+    let (operation, message) = NOPERATION
+        .lock()
+        .ok()
+        .and_then(|op| op.clone())
+        .unwrap_or_else(|| {
+            (
+                "Navigation".to_string(),
+                "Successfully completed navigation task".to_string(),
+            )
+        });
+
     LogEntry::new(
         "bot_001".to_string(),
         EventType::Info,
-        "Navigation".to_string(),
+        operation,
         Status::Success,
-        "Successfully completed navigation task".to_string(),
+        message,
     )
     .with_lidar(lidar_data)
     .with_location(location)
+    .with_coverage(coverage)
+    .with_vision(vision)
+    .with_swarm(swarm)
     .with_battery(battery)
     .with_sensors(sensors)
     .with_error(error)
@@ -74,3 +138,47 @@ pub async fn push_nav(spd: u64, tt: f64, dist: f64) {
         NDIST = dist;
     }
 }
+
+pub fn push_coverage(percentage: f64, seen: usize, total: usize) {
+    store_f64(&NCOVERAGE, percentage);
+    NSEEN.store(seen, Ordering::Relaxed);
+    NCELLS.store(total, Ordering::Relaxed);
+}
+
+pub fn push_operation(operation: &str, message: String) {
+    if let Ok(mut op) = NOPERATION.lock() {
+        *op = Some((operation.to_string(), message));
+    }
+}
+
+pub fn push_capture_fps(fps: f64) {
+    store_f64(&NCAPTURE_FPS, fps);
+}
+
+pub fn push_inference(fps: f64, dropped: u64, latency_ms: f64) {
+    store_f64(&NINFERENCE_FPS, fps);
+    NDROPPED.store(dropped, Ordering::Relaxed);
+    store_f64(&NINFERENCE_MS, latency_ms);
+}
+
+pub fn push_decision_latency(latency_ms: f64) {
+    store_f64(&NDECISION_MS, latency_ms);
+}
+
+pub fn push_swarm(accepted: u64, rejected: u64, unknown: u64, peers: Vec<SwarmPeer>) {
+    NSWARM_ACCEPTED.store(accepted, Ordering::Relaxed);
+    NSWARM_REJECTED.store(rejected, Ordering::Relaxed);
+    NSWARM_UNKNOWN.store(unknown, Ordering::Relaxed);
+
+    if let Ok(mut stats) = NSWARM_PEERS.lock() {
+        *stats = peers;
+    }
+}
+
+fn store_f64(value: &AtomicU64, x: f64) {
+    value.store(x.to_bits(), Ordering::Relaxed);
+}
+
+fn load_f64(value: &AtomicU64) -> f64 {
+    f64::from_bits(value.load(Ordering::Relaxed))
+}
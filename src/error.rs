@@ -8,6 +8,7 @@ pub enum Error {
     OnnxModelFileNotFound,
     InvalidJSONConfgiFile,
     CameraFailed,
+    CalibrationFailed(String),
     OtherError(anyhow::Error),
 }
 
@@ -17,6 +18,7 @@ impl Display for Error {
             Error::OnnxModelFileNotFound => write!(f, "ONNX model file not found"),
             Error::InvalidJSONConfgiFile => write!(f, "Invalid JSON config file"),
             Error::CameraFailed => write!(f, "Camera failed to open"),
+            Error::CalibrationFailed(reason) => write!(f, "Calibration failed: {}", reason),
             Error::OtherError(e) => write!(f, "{:?}", e),
         }
     }
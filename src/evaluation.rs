@@ -0,0 +1,780 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::XyXy;
+use crate::detector::{Detector, DetectorKind};
+use crate::error::Error;
+use crate::pipeline::FrameInfo;
+use crate::tracker::iou;
+use crate::yolo::{self, Detection};
+
+const USAGE: &str = "usage: evaluate <yolo | coco> <image dir> [--labels <dir>] \
+                     [--annotations <coco json>] [--out <report json>]";
+
+// IoU thresholds averaged for mAP@0.5:0.95
+const COCO_THRESHOLDS: [f32; 10] = [0.5, 0.55, 0.6, 0.65, 0.7, 0.75, 0.8, 0.85, 0.9, 0.95];
+
+/// A labeled object in the dataset
+#[derive(Debug, Clone)]
+struct GroundTruth {
+    class_id: usize,
+    bbox: XyXy,
+}
+
+struct Sample {
+    path: PathBuf,
+    truth: Vec<GroundTruth>,
+}
+
+/// Model output and labels for one image
+struct Evaluated {
+    truth: Vec<GroundTruth>,
+    detections: Vec<Detection>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClassReport {
+    pub name: String,
+    pub labels: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub ap50: f64,
+    pub ap50_95: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThresholdReport {
+    pub confidence: f32,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyReport {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Everything the evaluate subcommand measures
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub model_path: String,
+    pub images: usize,
+    /// confidence threshold the per class precision and recall are taken at
+    pub confidence_threshold: f32,
+    pub classes: Vec<ClassReport>,
+    pub map50: f64,
+    pub map50_95: f64,
+    /// rows are labels, columns predictions, the last of each is background
+    pub confusion_matrix: Vec<Vec<usize>>,
+    pub sweep: Vec<ThresholdReport>,
+    pub latency: LatencyReport,
+}
+
+enum Format {
+    Yolo,
+    Coco,
+}
+
+struct Args {
+    format: Format,
+    images: PathBuf,
+    labels: Option<PathBuf>,
+    annotations: Option<PathBuf>,
+    out: Option<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, Error> {
+    let invalid = || anyhow::anyhow!(USAGE);
+
+    let format = match args.first().map(String::as_str) {
+        Some("yolo") => Format::Yolo,
+        Some("coco") => Format::Coco,
+        _ => return Err(invalid().into()),
+    };
+    let images = PathBuf::from(args.get(1).ok_or_else(invalid)?);
+
+    let mut parsed = Args {
+        format,
+        images,
+        labels: None,
+        annotations: None,
+        out: None,
+    };
+
+    for pair in args[2..].chunks(2) {
+        let [flag, value] = pair else {
+            return Err(invalid().into());
+        };
+
+        match flag.as_str() {
+            "--labels" => parsed.labels = Some(PathBuf::from(value)),
+            "--annotations" => parsed.annotations = Some(PathBuf::from(value)),
+            "--out" => parsed.out = Some(PathBuf::from(value)),
+            _ => return Err(invalid().into()),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// `evaluate` subcommand, runs the configured detector over a labeled dataset
+pub fn run(args: &[String]) -> Result<(), Error> {
+    let args = parse_args(args)?;
+    let mut config = yolo::load_model_file()?;
+    // the report is labeled with what actually ran
+    let model_path = match config.detector {
+        DetectorKind::Yolo => config.model_path.clone(),
+        DetectorKind::Color => "color detector".to_string(),
+    };
+    let report_threshold = config.confidence_threshold;
+
+    let samples = match args.format {
+        Format::Yolo => {
+            let labels = args
+                .labels
+                .clone()
+                .unwrap_or_else(|| default_labels_dir(&args.images));
+            load_yolo(&args.images, &labels)?
+        }
+        Format::Coco => {
+            let annotations = args
+                .annotations
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("coco needs --annotations\n{}", USAGE))?;
+            load_coco(annotations, &args.images, &config.class_names)?
+        }
+    };
+
+    if samples.is_empty() {
+        return Err(anyhow::anyhow!("no labeled images in {}", args.images.display()).into());
+    }
+
+    // keep low confidence boxes, the sweep and mAP need them
+    config.confidence_threshold = 0.0;
+    let mut detector = Detector::configured(&config)?;
+
+    let mut evaluated = Vec::new();
+    let mut latencies = Vec::new();
+    for (n, sample) in samples.into_iter().enumerate() {
+        let img = match image::open(&sample.path) {
+            Ok(img) => img,
+            Err(e) => {
+                eprintln!("{}: {}", sample.path.display(), e);
+                continue;
+            }
+        };
+
+        let frame = FrameInfo::new(n as u64, &img);
+        let detections = detector.detect(&img, frame);
+        latencies.push(frame.age());
+
+        if (n + 1) % 50 == 0 {
+            println!("{} images evaluated", n + 1);
+        }
+
+        evaluated.push(Evaluated {
+            truth: sample.truth,
+            detections,
+        });
+    }
+
+    let class_names = class_names(&detector.class_names(), &evaluated);
+    let report = report(
+        model_path,
+        &class_names,
+        &evaluated,
+        &latencies,
+        report_threshold,
+    );
+    print_report(&report, &class_names);
+
+    if let Some(out) = &args.out {
+        serde_json::to_writer_pretty(File::create(out)?, &report)?;
+        println!("Report written to {}", out.display());
+    }
+
+    Ok(())
+}
+
+// datasets/x/images -> datasets/x/labels, anything else keeps labels next to the images
+fn default_labels_dir(images: &Path) -> PathBuf {
+    match images.file_name() {
+        Some(name) if name == "images" => images.with_file_name("labels"),
+        _ => images.to_path_buf(),
+    }
+}
+
+fn image_paths(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| image::ImageFormat::from_path(path).is_ok())
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// YOLO txt labels, one `class cx cy w h` line per object normalised to the
+/// image size. Images without a label file have no objects
+fn load_yolo(images: &Path, labels: &Path) -> Result<Vec<Sample>, Error> {
+    let mut samples = Vec::new();
+
+    for path in image_paths(images)? {
+        let Some(stem) = path.file_stem() else {
+            continue;
+        };
+        let label_path = labels.join(format!("{}.txt", stem.to_string_lossy()));
+        let (width, height) = image::image_dimensions(&path)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        let (width, height) = (width as f32, height as f32);
+
+        let content = fs::read_to_string(&label_path).unwrap_or_default();
+        let mut truth = Vec::new();
+
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parsed = (
+                fields.first().and_then(|f| f.parse::<usize>().ok()),
+                fields[1..]
+                    .iter()
+                    .take(4)
+                    .map(|f| f.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>(),
+            );
+
+            let (Some(class_id), Ok(values)) = parsed else {
+                eprintln!("{}: bad label line {:?}", label_path.display(), line);
+                continue;
+            };
+            let [cx, cy, w, h] = values[..] else {
+                eprintln!("{}: bad label line {:?}", label_path.display(), line);
+                continue;
+            };
+
+            truth.push(GroundTruth {
+                class_id,
+                bbox: (
+                    (cx - w / 2.0) * width,
+                    (cy - h / 2.0) * height,
+                    (cx + w / 2.0) * width,
+                    (cy + h / 2.0) * height,
+                ),
+            });
+        }
+
+        samples.push(Sample { path, truth });
+    }
+
+    Ok(samples)
+}
+
+#[derive(Deserialize)]
+struct CocoFile {
+    images: Vec<CocoImage>,
+    annotations: Vec<CocoAnnotation>,
+    categories: Vec<CocoCategory>,
+}
+
+#[derive(Deserialize)]
+struct CocoImage {
+    id: u64,
+    file_name: String,
+}
+
+#[derive(Deserialize)]
+struct CocoAnnotation {
+    image_id: u64,
+    category_id: u64,
+    /// x, y, width, height in pixels
+    bbox: [f32; 4],
+    #[serde(default)]
+    iscrowd: u8,
+}
+
+#[derive(Deserialize)]
+struct CocoCategory {
+    id: u64,
+    name: String,
+}
+
+/// COCO json annotations. Categories are matched to the model classes by name,
+/// or by their order in the file when the config has no class names
+fn load_coco(
+    annotations: &Path,
+    images: &Path,
+    class_names: &[String],
+) -> Result<Vec<Sample>, Error> {
+    let coco: CocoFile = serde_json::from_reader(BufReader::new(File::open(annotations)?))?;
+
+    let mut categories = HashMap::new();
+    for (index, category) in coco.categories.iter().enumerate() {
+        let class_id = if class_names.is_empty() {
+            Some(index)
+        } else {
+            class_names.iter().position(|name| *name == category.name)
+        };
+
+        match class_id {
+            Some(class_id) => {
+                categories.insert(category.id, class_id);
+            }
+            None => println!("Skipping category {}, not a model class", category.name),
+        }
+    }
+
+    let mut truth: HashMap<u64, Vec<GroundTruth>> = HashMap::new();
+    for annotation in coco.annotations.iter().filter(|a| a.iscrowd == 0) {
+        if let Some(&class_id) = categories.get(&annotation.category_id) {
+            let [x, y, w, h] = annotation.bbox;
+            truth
+                .entry(annotation.image_id)
+                .or_default()
+                .push(GroundTruth {
+                    class_id,
+                    bbox: (x, y, x + w, y + h),
+                });
+        }
+    }
+
+    Ok(coco
+        .images
+        .into_iter()
+        .map(|image| Sample {
+            path: images.join(&image.file_name),
+            truth: truth.remove(&image.id).unwrap_or_default(),
+        })
+        .collect())
+}
+
+fn class_names(model_names: &[String], evaluated: &[Evaluated]) -> Vec<String> {
+    let highest = evaluated
+        .iter()
+        .flat_map(|e| {
+            let truth = e.truth.iter().map(|t| t.class_id);
+            truth.chain(e.detections.iter().map(|d| d.class_id))
+        })
+        .max()
+        .map_or(0, |id| id + 1);
+
+    (0..highest.max(model_names.len()))
+        .map(|id| {
+            model_names.get(id).cloned().unwrap_or_else(|| {
+                // the model metadata names are on the detections themselves
+                evaluated
+                    .iter()
+                    .flat_map(|e| &e.detections)
+                    .find(|d| d.class_id == id)
+                    .map(|d| d.class_name.clone())
+                    .unwrap_or_else(|| format!("class {}", id))
+            })
+        })
+        .collect()
+}
+
+/// Greedily match the detections of every image to unmatched labels, best
+/// confidence first. Returns (confidence, true positive) for every detection
+/// of `class` at or above `min_confidence` and the number of labels of `class`
+fn match_class(
+    evaluated: &[Evaluated],
+    class: usize,
+    iou_threshold: f32,
+    min_confidence: f32,
+) -> (Vec<(f32, bool)>, usize) {
+    let mut scored = Vec::new();
+    let mut labels = 0;
+
+    for image in evaluated {
+        let truth: Vec<&GroundTruth> = image.truth.iter().filter(|t| t.class_id == class).collect();
+        labels += truth.len();
+        let mut matched = vec![false; truth.len()];
+
+        // detections are already sorted by confidence
+        for detection in image
+            .detections
+            .iter()
+            .filter(|d| d.class_id == class && d.confidence >= min_confidence)
+        {
+            let best = truth
+                .iter()
+                .enumerate()
+                .filter(|(t, _)| !matched[*t])
+                .map(|(t, gt)| (t, iou(gt.bbox, detection.bbox)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            let hit = match best {
+                Some((t, overlap)) if overlap >= iou_threshold => {
+                    matched[t] = true;
+                    true
+                }
+                _ => false,
+            };
+            scored.push((detection.confidence, hit));
+        }
+    }
+
+    (scored, labels)
+}
+
+/// Area under the precision recall curve with COCO's 101 point interpolation
+fn average_precision(mut scored: Vec<(f32, bool)>, labels: usize) -> f64 {
+    if labels == 0 {
+        return 0.0;
+    }
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut tp = 0;
+    let mut curve = Vec::with_capacity(scored.len());
+    for (n, (_, hit)) in scored.iter().enumerate() {
+        tp += usize::from(*hit);
+        curve.push((tp as f64 / labels as f64, tp as f64 / (n + 1) as f64));
+    }
+
+    // precision envelope, the best precision at this recall or higher
+    for n in (0..curve.len().saturating_sub(1)).rev() {
+        curve[n].1 = curve[n].1.max(curve[n + 1].1);
+    }
+
+    (0..=100)
+        .map(|r| {
+            let recall = r as f64 / 100.0;
+            curve
+                .iter()
+                .find(|(rec, _)| *rec >= recall)
+                .map_or(0.0, |(_, precision)| *precision)
+        })
+        .sum::<f64>()
+        / 101.0
+}
+
+fn precision_recall(scored: &[(f32, bool)], labels: usize) -> (f64, f64) {
+    let tp = scored.iter().filter(|(_, hit)| *hit).count() as f64;
+    let precision = if scored.is_empty() {
+        0.0
+    } else {
+        tp / scored.len() as f64
+    };
+    let recall = if labels == 0 { 0.0 } else { tp / labels as f64 };
+
+    (precision, recall)
+}
+
+/// Class agnostic matching at IoU 0.5 so wrong classes show up off the diagonal
+fn confusion_matrix(evaluated: &[Evaluated], classes: usize, threshold: f32) -> Vec<Vec<usize>> {
+    let background = classes;
+    let mut matrix = vec![vec![0; classes + 1]; classes + 1];
+
+    for image in evaluated {
+        let detections: Vec<&Detection> = image
+            .detections
+            .iter()
+            .filter(|d| d.confidence >= threshold)
+            .collect();
+
+        let mut pairs = Vec::new();
+        for (t, truth) in image.truth.iter().enumerate() {
+            for (d, detection) in detections.iter().enumerate() {
+                let overlap = iou(truth.bbox, detection.bbox);
+                if overlap >= 0.5 {
+                    pairs.push((overlap, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut truth_matched = vec![false; image.truth.len()];
+        let mut detection_matched = vec![false; detections.len()];
+        for (_, t, d) in pairs {
+            if truth_matched[t] || detection_matched[d] {
+                continue;
+            }
+            truth_matched[t] = true;
+            detection_matched[d] = true;
+            matrix[image.truth[t].class_id.min(background)]
+                [detections[d].class_id.min(background)] += 1;
+        }
+
+        for (t, truth) in image.truth.iter().enumerate() {
+            if !truth_matched[t] {
+                matrix[truth.class_id.min(background)][background] += 1;
+            }
+        }
+        for (d, detection) in detections.iter().enumerate() {
+            if !detection_matched[d] {
+                matrix[background][detection.class_id.min(background)] += 1;
+            }
+        }
+    }
+
+    matrix
+}
+
+fn latency(latencies: &[Duration]) -> LatencyReport {
+    let mut ms: Vec<f64> = latencies.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+    ms.sort_by(f64::total_cmp);
+
+    let percentile = |p: f64| {
+        if ms.is_empty() {
+            0.0
+        } else {
+            ms[((ms.len() - 1) as f64 * p).round() as usize]
+        }
+    };
+
+    LatencyReport {
+        mean_ms: ms.iter().sum::<f64>() / ms.len().max(1) as f64,
+        p50_ms: percentile(0.5),
+        p95_ms: percentile(0.95),
+        max_ms: ms.last().copied().unwrap_or(0.0),
+    }
+}
+
+fn report(
+    model_path: String,
+    class_names: &[String],
+    evaluated: &[Evaluated],
+    latencies: &[Duration],
+    threshold: f32,
+) -> Report {
+    let classes: Vec<ClassReport> = class_names
+        .iter()
+        .enumerate()
+        .map(|(class, name)| {
+            let (scored, labels) = match_class(evaluated, class, 0.5, 0.0);
+            let ap50 = average_precision(scored, labels);
+            let ap50_95 = COCO_THRESHOLDS
+                .iter()
+                .map(|&t| {
+                    let (scored, labels) = match_class(evaluated, class, t, 0.0);
+                    average_precision(scored, labels)
+                })
+                .sum::<f64>()
+                / COCO_THRESHOLDS.len() as f64;
+
+            let (scored, labels) = match_class(evaluated, class, 0.5, threshold);
+            let (precision, recall) = precision_recall(&scored, labels);
+
+            ClassReport {
+                name: name.clone(),
+                labels,
+                precision,
+                recall,
+                ap50,
+                ap50_95,
+            }
+        })
+        .collect();
+
+    // classes without labels have no defined AP
+    let labeled: Vec<&ClassReport> = classes.iter().filter(|c| c.labels > 0).collect();
+    let mean = |ap: fn(&ClassReport) -> f64| {
+        labeled.iter().map(|c| ap(c)).sum::<f64>() / labeled.len().max(1) as f64
+    };
+    let map50 = mean(|c| c.ap50);
+    let map50_95 = mean(|c| c.ap50_95);
+
+    let sweep = (1..20)
+        .map(|step| {
+            let confidence = step as f32 * 0.05;
+            let (mut scored, mut labels) = (Vec::new(), 0);
+            for class in 0..class_names.len() {
+                let (class_scored, class_labels) = match_class(evaluated, class, 0.5, confidence);
+                scored.extend(class_scored);
+                labels += class_labels;
+            }
+
+            let (precision, recall) = precision_recall(&scored, labels);
+            let f1 = if precision + recall > 0.0 {
+                2.0 * precision * recall / (precision + recall)
+            } else {
+                0.0
+            };
+
+            ThresholdReport {
+                confidence,
+                precision,
+                recall,
+                f1,
+            }
+        })
+        .collect();
+
+    Report {
+        model_path,
+        images: evaluated.len(),
+        confidence_threshold: threshold,
+        classes,
+        map50,
+        map50_95,
+        confusion_matrix: confusion_matrix(evaluated, class_names.len(), threshold),
+        sweep,
+        latency: latency(latencies),
+    }
+}
+
+fn print_report(report: &Report, class_names: &[String]) {
+    println!("\n{} on {} images", report.model_path, report.images);
+    println!(
+        "\n{:<16} {:>7} {:>9} {:>9} {:>9} {:>9}",
+        "class", "labels", "precision", "recall", "AP50", "AP50-95"
+    );
+    for class in &report.classes {
+        println!(
+            "{:<16} {:>7} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+            class.name, class.labels, class.precision, class.recall, class.ap50, class.ap50_95
+        );
+    }
+    println!(
+        "{:<16} {:>7} {:>9} {:>9} {:>9.3} {:>9.3}",
+        "all", "", "", "", report.map50, report.map50_95
+    );
+    println!(
+        "(precision and recall at confidence {:.2})",
+        report.confidence_threshold
+    );
+
+    println!("\nconfusion matrix, rows are labels, columns predictions");
+    let mut header = format!("{:<16}", "");
+    for name in class_names.iter().map(String::as_str).chain(["background"]) {
+        header.push_str(&format!(" {:>10.10}", name));
+    }
+    println!("{}", header);
+    for (name, row) in class_names
+        .iter()
+        .map(String::as_str)
+        .chain(["background"])
+        .zip(&report.confusion_matrix)
+    {
+        let mut line = format!("{:<16.16}", name);
+        for count in row {
+            line.push_str(&format!(" {:>10}", count));
+        }
+        println!("{}", line);
+    }
+
+    println!(
+        "\n{:>10} {:>9} {:>9} {:>9}",
+        "confidence", "precision", "recall", "f1"
+    );
+    let best = report.sweep.iter().max_by(|a, b| a.f1.total_cmp(&b.f1));
+    for row in &report.sweep {
+        let marker = if best.is_some_and(|best| best.confidence == row.confidence) {
+            " <- best f1"
+        } else {
+            ""
+        };
+        println!(
+            "{:>10.2} {:>9.3} {:>9.3} {:>9.3}{}",
+            row.confidence, row.precision, row.recall, row.f1, marker
+        );
+    }
+
+    let latency = &report.latency;
+    println!(
+        "\nlatency mean {:.1}ms p50 {:.1}ms p95 {:.1}ms max {:.1}ms ({:.1} fps)",
+        latency.mean_ms,
+        latency.p50_ms,
+        latency.p95_ms,
+        latency.max_ms,
+        1000.0 / latency.mean_ms.max(f64::EPSILON)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+
+    fn detection(class_id: usize, confidence: f32, bbox: XyXy) -> Detection {
+        Detection {
+            bbox,
+            class_id,
+            class_name: class_id.to_string(),
+            confidence,
+            frame: FrameInfo::new(0, &DynamicImage::new_rgb8(1, 1)),
+            mask: None,
+        }
+    }
+
+    #[test]
+    fn perfect_detections_have_ap_one() {
+        let ap = average_precision(vec![(0.9, true), (0.8, true)], 2);
+
+        assert!((ap - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ap_is_zero_without_labels_or_hits() {
+        assert_eq!(average_precision(vec![(0.9, true)], 0), 0.0);
+        assert_eq!(average_precision(vec![(0.9, false), (0.5, false)], 3), 0.0);
+    }
+
+    #[test]
+    fn ap_only_counts_the_recall_reached() {
+        // recall never gets past 0.5, so only 51 of the 101 points count
+        let ap = average_precision(vec![(0.9, true), (0.8, false)], 2);
+
+        assert!((ap - 51.0 / 101.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ap_sorts_by_confidence_and_uses_the_precision_envelope() {
+        // the hit only comes after a false positive, precision 0.5 at recall 1
+        let ap = average_precision(vec![(0.8, true), (0.9, false)], 1);
+
+        assert!((ap - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn precision_and_recall_count_hits() {
+        let (precision, recall) = precision_recall(&[(0.9, true), (0.8, false)], 4);
+
+        assert!((precision - 0.5).abs() < 1e-9);
+        assert!((recall - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_label_is_only_matched_once() {
+        let evaluated = [Evaluated {
+            truth: vec![GroundTruth {
+                class_id: 0,
+                bbox: (0.0, 0.0, 10.0, 10.0),
+            }],
+            detections: vec![
+                detection(0, 0.9, (0.0, 0.0, 10.0, 10.0)),
+                detection(0, 0.8, (1.0, 1.0, 10.0, 10.0)),
+                detection(1, 0.7, (0.0, 0.0, 10.0, 10.0)),
+            ],
+        }];
+
+        let (scored, labels) = match_class(&evaluated, 0, 0.5, 0.0);
+
+        assert_eq!(labels, 1);
+        assert_eq!(scored, vec![(0.9, true), (0.8, false)]);
+    }
+
+    #[test]
+    fn low_overlap_is_a_false_positive() {
+        let evaluated = [Evaluated {
+            truth: vec![GroundTruth {
+                class_id: 0,
+                bbox: (0.0, 0.0, 10.0, 10.0),
+            }],
+            detections: vec![detection(0, 0.9, (8.0, 8.0, 18.0, 18.0))],
+        }];
+
+        let (scored, _) = match_class(&evaluated, 0, 0.5, 0.0);
+
+        assert_eq!(scored, vec![(0.9, false)]);
+    }
+}
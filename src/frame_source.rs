@@ -0,0 +1,319 @@
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, Rgb, RgbImage};
+use nokhwa::{Camera, pixel_format::RgbFormat};
+use serde::Deserialize;
+
+use crate::camera::{self, CameraConfig};
+use crate::error::Error;
+
+/// Where the vision pipeline gets its frames from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// the V4L2 webcam
+    Camera,
+    /// a video file decoded by ffmpeg, which has to be on the PATH
+    Video,
+    /// every image in a directory, in name order
+    Folder,
+    /// a generated scene with a ball moving around
+    Synthetic,
+}
+
+/// Frame source section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SourceConfig {
+    pub kind: SourceKind,
+    /// video file or image directory
+    pub path: String,
+    /// playback rate of the video, folder and synthetic sources
+    pub fps: f64,
+    /// start over once the video or folder runs out
+    pub looping: bool,
+    /// size of the video and synthetic frames
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self {
+            kind: SourceKind::Camera,
+            path: String::new(),
+            fps: 30.0,
+            looping: false,
+            width: 640,
+            height: 480,
+        }
+    }
+}
+
+/// A frame and when the source got hold of it, taken before decoding so
+/// decode time counts towards the frame's age
+pub struct Grabbed {
+    pub image: DynamicImage,
+    pub captured: Instant,
+}
+
+/// Anything that produces frames for the vision pipeline
+pub trait FrameSource {
+    /// Next frame, None once the source is exhausted
+    fn next_frame(&mut self) -> Result<Option<Grabbed>, Error>;
+}
+
+/// Open the source selected in the config
+pub fn open(config: &SourceConfig, camera: &CameraConfig) -> Result<Box<dyn FrameSource>, Error> {
+    let source: Box<dyn FrameSource> = match config.kind {
+        SourceKind::Camera => Box::new(CameraSource::new(camera)?),
+        SourceKind::Video => Box::new(VideoSource::new(config)?),
+        SourceKind::Folder => Box::new(FolderSource::new(config)?),
+        SourceKind::Synthetic => Box::new(SyntheticSource::new(config)),
+    };
+
+    Ok(source)
+}
+
+/// Sleeps just long enough to hand out frames at a fixed rate
+struct Pacer {
+    interval: Duration,
+    next: Instant,
+}
+
+impl Pacer {
+    fn new(fps: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / fps.max(0.1)),
+            next: Instant::now(),
+        }
+    }
+
+    fn wait(&mut self) {
+        let now = Instant::now();
+        if self.next > now {
+            sleep(self.next - now);
+        }
+        // never try to catch up on frames we were too slow for
+        self.next = self.next.max(now) + self.interval;
+    }
+}
+
+pub struct CameraSource {
+    camera: Camera,
+}
+
+impl CameraSource {
+    pub fn new(config: &CameraConfig) -> Result<Self, Error> {
+        let mut camera = camera::open_camera(config)?;
+        camera.open_stream()?;
+
+        Ok(Self { camera })
+    }
+}
+
+impl FrameSource for CameraSource {
+    fn next_frame(&mut self) -> Result<Option<Grabbed>, Error> {
+        let buffer = self.camera.frame()?;
+        let captured = Instant::now();
+        let img = buffer.decode_image::<RgbFormat>()?;
+
+        Ok(Some(Grabbed {
+            image: DynamicImage::ImageRgb8(img),
+            captured,
+        }))
+    }
+}
+
+/// Video file decoded to raw rgb by an ffmpeg child process, which also takes
+/// care of playing it back in real time
+pub struct VideoSource {
+    child: Child,
+    stdout: ChildStdout,
+    width: u32,
+    height: u32,
+}
+
+impl VideoSource {
+    pub fn new(config: &SourceConfig) -> Result<Self, Error> {
+        // ffmpeg is a runtime dependency of this source only, say so up front
+        // rather than failing on the first frame
+        let found = Command::new("ffmpeg")
+            .arg("-version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !found {
+            return Err(anyhow::anyhow!(
+                "the video source needs ffmpeg, install it and make sure it is on the PATH"
+            )
+            .into());
+        }
+
+        let mut command = Command::new("ffmpeg");
+        command.args(["-loglevel", "error", "-re"]);
+        if config.looping {
+            command.args(["-stream_loop", "-1"]);
+        }
+        command
+            .args(["-i", &config.path])
+            .args([
+                "-vf",
+                &format!(
+                    "scale={}:{},fps={}",
+                    config.width, config.height, config.fps
+                ),
+            ])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped());
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("failed to start ffmpeg for {}: {}", config.path, e))?;
+        let stdout = child.stdout.take().ok_or(Error::CameraFailed)?;
+
+        Ok(Self {
+            child,
+            stdout,
+            width: config.width,
+            height: config.height,
+        })
+    }
+}
+
+impl FrameSource for VideoSource {
+    fn next_frame(&mut self) -> Result<Option<Grabbed>, Error> {
+        let mut buf = vec![0; (self.width * self.height * 3) as usize];
+
+        match self.stdout.read_exact(&mut buf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(anyhow::Error::from(e).into()),
+        }
+        let captured = Instant::now();
+
+        let img = RgbImage::from_raw(self.width, self.height, buf).ok_or(Error::CameraFailed)?;
+
+        Ok(Some(Grabbed {
+            image: DynamicImage::ImageRgb8(img),
+            captured,
+        }))
+    }
+}
+
+impl Drop for VideoSource {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Images of a directory played back at a fixed rate
+pub struct FolderSource {
+    paths: Vec<PathBuf>,
+    index: usize,
+    looping: bool,
+    pacer: Pacer,
+}
+
+impl FolderSource {
+    pub fn new(config: &SourceConfig) -> Result<Self, Error> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&config.path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {}", config.path, e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| image::ImageFormat::from_path(path).is_ok())
+            .collect();
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!("no images in {}", config.path).into());
+        }
+
+        Ok(Self {
+            paths,
+            index: 0,
+            looping: config.looping,
+            pacer: Pacer::new(config.fps),
+        })
+    }
+}
+
+impl FrameSource for FolderSource {
+    fn next_frame(&mut self) -> Result<Option<Grabbed>, Error> {
+        if self.index == self.paths.len() {
+            if !self.looping {
+                return Ok(None);
+            }
+            self.index = 0;
+        }
+
+        self.pacer.wait();
+        let captured = Instant::now();
+        let path = &self.paths[self.index];
+        self.index += 1;
+
+        let image = image::open(path)
+            .map_err(|e| anyhow::anyhow!("failed to decode {}: {}", path.display(), e))?;
+
+        Ok(Some(Grabbed { image, captured }))
+    }
+}
+
+/// Orange ball circling over a grey floor, enough to exercise the pipeline
+/// and the nav response without any hardware
+pub struct SyntheticSource {
+    width: u32,
+    height: u32,
+    frame: u64,
+    pacer: Pacer,
+}
+
+impl SyntheticSource {
+    pub fn new(config: &SourceConfig) -> Self {
+        Self {
+            width: config.width,
+            height: config.height,
+            frame: 0,
+            pacer: Pacer::new(config.fps),
+        }
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn next_frame(&mut self) -> Result<Option<Grabbed>, Error> {
+        self.pacer.wait();
+        let captured = Instant::now();
+
+        let (w, h) = (self.width as f64, self.height as f64);
+        // one lap every 10 seconds at 30fps
+        let angle = self.frame as f64 * std::f64::consts::TAU / 300.0;
+        let center = (
+            w / 2.0 + w / 3.0 * angle.cos(),
+            h / 2.0 + h / 4.0 * angle.sin(),
+        );
+        let radius = h / 12.0;
+        self.frame += 1;
+
+        let img = RgbImage::from_fn(self.width, self.height, |x, y| {
+            let d = (x as f64 - center.0).hypot(y as f64 - center.1);
+            if d <= radius {
+                Rgb([255, 120, 0])
+            } else {
+                Rgb([110, 110, 110])
+            }
+        });
+
+        Ok(Some(Grabbed {
+            image: DynamicImage::ImageRgb8(img),
+            captured,
+        }))
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+use crate::grid::{CellState, OccupancyGrid};
+
+/// Exploration section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ExplorationConfig {
+    /// frontiers with fewer cells than this are ignored as lidar noise
+    pub min_frontier_size: usize,
+    /// score added per unknown cell bordering the frontier
+    pub gain_weight: f64,
+    /// score removed per meter of straight line distance to the frontier
+    pub distance_weight: f64,
+    /// how many of the best frontiers we try to plan to before giving up
+    pub max_candidates: usize,
+    /// below this many known cells the map is still empty, not explored
+    pub min_known_cells: usize,
+    /// full turns in place to seed an empty map before giving up on exploring
+    pub seed_turns: u32,
+    /// frontiers with centroids this close (meters) count as the same one
+    pub blacklist_radius: f64,
+    /// times we head for a frontier before giving up on it, whether we
+    /// couldn't reach it or it was still a frontier once we got there
+    pub max_attempts: u32,
+}
+
+impl Default for ExplorationConfig {
+    fn default() -> Self {
+        Self {
+            min_frontier_size: 5,
+            gain_weight: 1.0,
+            distance_weight: 10.0,
+            max_candidates: 5,
+            min_known_cells: 200,
+            seed_turns: 2,
+            blacklist_radius: 0.3,
+            max_attempts: 2,
+        }
+    }
+}
+
+/// A connected group of free cells bordering unknown space
+#[derive(Debug, Clone)]
+pub struct Frontier {
+    /// center of the frontier in the odom frame
+    pub centroid: (f64, f64),
+    /// number of cells in the frontier
+    pub size: usize,
+    /// number of unknown cells next to the frontier, the information gain
+    pub gain: usize,
+    pub score: f64,
+}
+
+/// Frontiers exploration already headed for, so one it can't clear (no path
+/// to it, or a sensor shadow that stays unknown) is not picked forever
+#[derive(Debug, Default)]
+pub struct Blacklist {
+    /// centroid of every frontier we tried and how often
+    attempts: Vec<((f64, f64), u32)>,
+}
+
+impl Blacklist {
+    /// Count another attempt at the frontier around `centroid`
+    pub fn record(&mut self, centroid: (f64, f64), config: &ExplorationConfig) {
+        match self.nearest(centroid, config) {
+            Some(n) => self.attempts[n].1 += 1,
+            None => self.attempts.push((centroid, 1)),
+        }
+    }
+
+    /// Whether we tried the frontier around `centroid` too often already
+    pub fn is_blocked(&self, centroid: (f64, f64), config: &ExplorationConfig) -> bool {
+        self.nearest(centroid, config)
+            .is_some_and(|n| self.attempts[n].1 >= config.max_attempts)
+    }
+
+    fn nearest(&self, centroid: (f64, f64), config: &ExplorationConfig) -> Option<usize> {
+        let distance = |(x, y): (f64, f64)| (x - centroid.0).hypot(y - centroid.1);
+
+        self.attempts
+            .iter()
+            .enumerate()
+            .filter(|(_, (c, _))| distance(*c) <= config.blacklist_radius)
+            .min_by(|(_, (a, _)), (_, (b, _))| distance(*a).total_cmp(&distance(*b)))
+            .map(|(n, _)| n)
+    }
+}
+
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Find every frontier in the grid, best scored first
+pub fn find(grid: &OccupancyGrid, pose: (f64, f64), config: &ExplorationConfig) -> Vec<Frontier> {
+    let mut visited = vec![false; grid.width * grid.height];
+    let mut frontiers = Vec::new();
+
+    for cy in 0..grid.height {
+        for cx in 0..grid.width {
+            if visited[grid.index(cx, cy)] || !is_frontier(grid, cx, cy) {
+                continue;
+            }
+
+            let cells = grow(grid, &mut visited, (cx, cy));
+            if cells.len() < config.min_frontier_size {
+                continue;
+            }
+
+            let gain = unknown_neighbours(grid, &cells);
+            let (sum_x, sum_y) = cells.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| {
+                let (wx, wy) = grid.cell_to_world(x, y);
+                (sx + wx, sy + wy)
+            });
+            let centroid = (sum_x / cells.len() as f64, sum_y / cells.len() as f64);
+            let distance = (centroid.0 - pose.0).hypot(centroid.1 - pose.1);
+            let score = config.gain_weight * gain as f64 - config.distance_weight * distance;
+
+            frontiers.push(Frontier {
+                centroid,
+                size: cells.len(),
+                gain,
+                score,
+            });
+        }
+    }
+
+    frontiers.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    frontiers
+}
+
+// a free cell with at least one unknown neighbour
+fn is_frontier(grid: &OccupancyGrid, cx: usize, cy: usize) -> bool {
+    grid.state(cx, cy) == CellState::Free
+        && neighbours(grid, cx, cy).any(|(nx, ny)| grid.state(nx, ny) == CellState::Unknown)
+}
+
+// flood fill the frontier cells connected to `start`
+fn grow(grid: &OccupancyGrid, visited: &mut [bool], start: (usize, usize)) -> Vec<(usize, usize)> {
+    let mut cells = Vec::new();
+    let mut queue = VecDeque::from([start]);
+    visited[grid.index(start.0, start.1)] = true;
+
+    while let Some((cx, cy)) = queue.pop_front() {
+        cells.push((cx, cy));
+
+        for (nx, ny) in neighbours(grid, cx, cy) {
+            let idx = grid.index(nx, ny);
+            if !visited[idx] && is_frontier(grid, nx, ny) {
+                visited[idx] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    cells
+}
+
+fn unknown_neighbours(grid: &OccupancyGrid, cells: &[(usize, usize)]) -> usize {
+    let mut counted = vec![false; grid.width * grid.height];
+    let mut gain = 0;
+
+    for &(cx, cy) in cells {
+        for (nx, ny) in neighbours(grid, cx, cy) {
+            let idx = grid.index(nx, ny);
+            if !counted[idx] && grid.state(nx, ny) == CellState::Unknown {
+                counted[idx] = true;
+                gain += 1;
+            }
+        }
+    }
+
+    gain
+}
+
+fn neighbours(
+    grid: &OccupancyGrid,
+    cx: usize,
+    cy: usize,
+) -> impl Iterator<Item = (usize, usize)> + '_ {
+    NEIGHBOURS.iter().filter_map(move |&(dx, dy)| {
+        let nx = cx as i64 + dx;
+        let ny = cy as i64 + dy;
+
+        (nx >= 0 && ny >= 0 && nx < grid.width as i64 && ny < grid.height as i64)
+            .then_some((nx as usize, ny as usize))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frontier_is_blocked_after_max_attempts() {
+        let config = ExplorationConfig::default();
+        let mut blacklist = Blacklist::default();
+
+        for _ in 0..config.max_attempts {
+            assert!(!blacklist.is_blocked((1.0, 1.0), &config));
+            blacklist.record((1.0, 1.0), &config);
+        }
+
+        assert!(blacklist.is_blocked((1.0, 1.0), &config));
+    }
+
+    #[test]
+    fn nearby_centroids_are_the_same_frontier() {
+        let config = ExplorationConfig::default();
+        let mut blacklist = Blacklist::default();
+
+        // the centroid shifts a little as the map fills in
+        blacklist.record((1.0, 1.0), &config);
+        blacklist.record((1.1, 0.95), &config);
+
+        assert!(blacklist.is_blocked((1.05, 1.0), &config));
+        assert!(!blacklist.is_blocked((2.0, 1.0), &config));
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::{Arc, Mutex};
+
+use r2r::sensor_msgs::msg::LaserScan;
+use serde::Deserialize;
+
+use crate::odom::OdomData;
+
+/// Shared occupancy grid, written by the lidar process and read by navigation
+pub type SharedGrid = Arc<Mutex<OccupancyGrid>>;
+
+// log odds added to a cell for every hit / miss and the clamp around them
+const LOG_ODDS_HIT: f32 = 0.85;
+const LOG_ODDS_MISS: f32 = -0.4;
+const LOG_ODDS_MAX: f32 = 3.5;
+const LOG_ODDS_OCCUPIED: f32 = 0.6;
+const LOG_ODDS_FREE: f32 = -0.6;
+
+/// Map section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MapConfig {
+    /// size of a single cell in meters
+    pub resolution: f64,
+    /// number of cells along x
+    pub width: usize,
+    /// number of cells along y
+    pub height: usize,
+    /// world x coordinate of the bottom left corner of the grid
+    pub origin_x: f64,
+    /// world y coordinate of the bottom left corner of the grid
+    pub origin_y: f64,
+    /// lidar returns further than this are only used to clear cells
+    pub max_range: f32,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        // 10m x 10m arena centered on the odom origin
+        Self {
+            resolution: 0.05,
+            width: 200,
+            height: 200,
+            origin_x: -5.0,
+            origin_y: -5.0,
+            max_range: 3.5,
+        }
+    }
+}
+
+/// What we know about a single cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellState {
+    Unknown,
+    Free,
+    Occupied,
+}
+
+/// Log odds occupancy grid in the odom frame
+#[derive(Debug, Clone)]
+pub struct OccupancyGrid {
+    pub resolution: f64,
+    pub width: usize,
+    pub height: usize,
+    pub origin_x: f64,
+    pub origin_y: f64,
+    max_range: f32,
+    cells: Vec<f32>,
+}
+
+impl OccupancyGrid {
+    pub fn new(config: &MapConfig) -> Self {
+        Self {
+            resolution: config.resolution,
+            width: config.width,
+            height: config.height,
+            origin_x: config.origin_x,
+            origin_y: config.origin_y,
+            max_range: config.max_range,
+            cells: vec![0.0; config.width * config.height],
+        }
+    }
+
+    pub fn shared(config: &MapConfig) -> SharedGrid {
+        Arc::new(Mutex::new(Self::new(config)))
+    }
+
+    /// world coordinates to cell coordinates, None if outside the grid
+    pub fn world_to_cell(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let cx = ((x - self.origin_x) / self.resolution).floor();
+        let cy = ((y - self.origin_y) / self.resolution).floor();
+
+        if cx < 0.0 || cy < 0.0 || cx >= self.width as f64 || cy >= self.height as f64 {
+            return None;
+        }
+
+        Some((cx as usize, cy as usize))
+    }
+
+    /// center of a cell in world coordinates
+    pub fn cell_to_world(&self, cx: usize, cy: usize) -> (f64, f64) {
+        (
+            self.origin_x + (cx as f64 + 0.5) * self.resolution,
+            self.origin_y + (cy as f64 + 0.5) * self.resolution,
+        )
+    }
+
+    pub fn index(&self, cx: usize, cy: usize) -> usize {
+        cy * self.width + cx
+    }
+
+    pub fn state(&self, cx: usize, cy: usize) -> CellState {
+        let value = self.cells[self.index(cx, cy)];
+
+        if value >= LOG_ODDS_OCCUPIED {
+            CellState::Occupied
+        } else if value <= LOG_ODDS_FREE {
+            CellState::Free
+        } else {
+            CellState::Unknown
+        }
+    }
+
+    /// number of cells that are no longer unknown
+    pub fn known_cells(&self) -> usize {
+        self.cells
+            .iter()
+            .filter(|&&value| value >= LOG_ODDS_OCCUPIED || value <= LOG_ODDS_FREE)
+            .count()
+    }
+
+    fn update_cell(&mut self, cx: usize, cy: usize, delta: f32) {
+        let idx = self.index(cx, cy);
+        self.cells[idx] = (self.cells[idx] + delta).clamp(-LOG_ODDS_MAX, LOG_ODDS_MAX);
+    }
+
+    /// Ray cast every lidar return from the bot pose, clearing cells along the
+    /// beam and marking the cell the beam ended in as occupied
+    pub fn integrate_scan(&mut self, scan: &LaserScan, pose: &OdomData) {
+        let Some(start) = self.world_to_cell(pose.x1, pose.y1) else {
+            return;
+        };
+
+        let yaw = pose.yaw();
+
+        for (i, &range) in scan.ranges.iter().enumerate() {
+            if range.is_nan() || range < scan.range_min {
+                continue;
+            }
+
+            // anything past max range is treated as a miss at max range
+            let hit = range.is_finite() && range <= self.max_range.min(scan.range_max);
+            let length = f64::from(if hit { range } else { self.max_range });
+
+            let angle = yaw + (scan.angle_min + i as f32 * scan.angle_increment) as f64;
+            let end_x = pose.x1 + length * angle.cos();
+            let end_y = pose.y1 + length * angle.sin();
+
+            let end = (
+                ((end_x - self.origin_x) / self.resolution).floor() as i64,
+                ((end_y - self.origin_y) / self.resolution).floor() as i64,
+            );
+
+            self.trace(start, end, hit);
+        }
+    }
+
+    // bresenham line from start to end
+    fn trace(&mut self, start: (usize, usize), end: (i64, i64), hit: bool) {
+        let (mut x, mut y) = (start.0 as i64, start.1 as i64);
+        let dx = (end.0 - x).abs();
+        let dy = -(end.1 - y).abs();
+        let sx = if x < end.0 { 1 } else { -1 };
+        let sy = if y < end.1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+                return;
+            }
+
+            if (x, y) == end {
+                let delta = if hit { LOG_ODDS_HIT } else { LOG_ODDS_MISS };
+                self.update_cell(x as usize, y as usize, delta);
+                return;
+            }
+
+            self.update_cell(x as usize, y as usize, LOG_ODDS_MISS);
+
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt::{Display, Formatter};
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Home section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HomeConfig {
+    /// drive back to the start pose once a charm has been collected
+    pub return_after_collect: bool,
+    /// seconds after startup before the bot gives up searching and goes home
+    pub mission_timeout_secs: Option<u64>,
+    /// seconds to get home before stopping wherever we are
+    pub return_timeout_secs: f64,
+}
+
+impl Default for HomeConfig {
+    fn default() -> Self {
+        Self {
+            return_after_collect: true,
+            mission_timeout_secs: None,
+            return_timeout_secs: 120.0,
+        }
+    }
+}
+
+impl HomeConfig {
+    pub fn return_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.return_timeout_secs.max(0.0))
+    }
+}
+
+/// Why the bot is heading back to where it started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnReason {
+    CharmCollected,
+    MissionTimeout,
+    OperatorCommand,
+}
+
+impl Display for ReturnReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReturnReason::CharmCollected => write!(f, "charm collected"),
+            ReturnReason::MissionTimeout => write!(f, "mission timeout"),
+            ReturnReason::OperatorCommand => write!(f, "operator command"),
+        }
+    }
+}
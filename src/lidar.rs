@@ -5,8 +5,11 @@ use async_cell::sync::AsyncCell;
 use futures::Stream;
 use futures::stream::StreamExt;
 use r2r::sensor_msgs::msg::LaserScan;
+use tokio::sync::watch;
 
 use crate::documenter;
+use crate::grid::SharedGrid;
+use crate::odom::OdomData;
 
 #[derive(Debug)]
 pub struct Direction {
@@ -23,9 +26,19 @@ pub struct Direction {
 pub async fn lidar_scan<T: Stream<Item = LaserScan> + Unpin>(
     mut stream: T,
     tx: Arc<AsyncCell<LaserScan>>,
+    pose_rx: watch::Receiver<Option<OdomData>>,
+    grid: SharedGrid,
 ) {
     loop {
         if let Some(msg) = stream.next().await {
+            // map the scan from the latest pose, skip it until odom has arrived
+            let pose = *pose_rx.borrow();
+            if let Some(pose) = pose
+                && let Ok(mut grid) = grid.lock()
+            {
+                grid.integrate_scan(&msg, &pose);
+            }
+
             tx.set(msg);
         }
     }
@@ -33,6 +33,52 @@ pub struct Location {
     pub travel_time: f64,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Coverage {
+    pub percentage: f64,
+    pub seen_cells: usize,
+    pub total_cells: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Vision {
+    pub capture_fps: f64,
+    pub inference_fps: f64,
+    pub dropped_frames: u64,
+    /// capture to detections ready
+    pub inference_latency_ms: f64,
+    /// capture to nav acting on the detection
+    pub decision_latency_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwarmPeer {
+    pub addr: String,
+    /// bot id, once the peer was heard from
+    pub id: Option<String>,
+    pub capabilities: Vec<String>,
+    /// found on the multicast group rather than configured
+    pub discovered: bool,
+    /// seconds since the peer was last heard from
+    pub last_seen_secs: Option<f64>,
+    /// missed its heartbeats
+    pub stale: bool,
+    pub sent: u64,
+    pub send_failed: u64,
+    pub received: u64,
+    pub rejected: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Swarm {
+    pub accepted_messages: u64,
+    /// unauthenticated, wrong version or malformed
+    pub rejected_messages: u64,
+    /// datagrams from addresses that are not configured peers
+    pub unknown_senders: u64,
+    pub peers: Vec<SwarmPeer>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Battery {
     pub level: f64,
@@ -71,6 +117,9 @@ pub struct LogEntry {
     pub message: String,
     pub lidar: Option<LidarData>,
     pub location: Option<Location>,
+    pub coverage: Option<Coverage>,
+    pub vision: Option<Vision>,
+    pub swarm: Option<Swarm>,
     pub battery: Option<Battery>,
     pub sensors: Option<Sensors>,
     pub error: Option<ErrorDetails>,
@@ -93,6 +142,9 @@ impl LogEntry {
             message,
             lidar: None,
             location: None,
+            coverage: None,
+            vision: None,
+            swarm: None,
             battery: None,
             sensors: None,
             error: None,
@@ -107,6 +159,21 @@ impl LogEntry {
         self
     }
 
+    pub fn with_coverage(mut self, coverage: Coverage) -> Self {
+        self.coverage = Some(coverage);
+        self
+    }
+
+    pub fn with_vision(mut self, vision: Vision) -> Self {
+        self.vision = Some(vision);
+        self
+    }
+
+    pub fn with_swarm(mut self, swarm: Swarm) -> Self {
+        self.swarm = Some(swarm);
+        self
+    }
+
     pub fn with_battery(mut self, battery: Battery) -> Self {
         self.battery = Some(battery);
         self
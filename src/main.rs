@@ -1,9 +1,31 @@
+/// Drawing detections onto debug frames
+mod annotate;
+/// Intrinsic calibration subcommand
+mod calibration;
 /// Camera logic
 mod camera;
+/// Checkerboard corner detection
+mod checkerboard;
+/// HSV color blob detector
+mod color;
+/// Coverage search of the arena
+mod coverage;
+/// YOLO or color detector selection
+mod detector;
 /// documenter module
 mod documenter;
 /// Graceful error handling
 mod error;
+/// Offline detection metrics subcommand
+mod evaluation;
+/// Camera, video, image folder and synthetic frame sources
+mod frame_source;
+/// Frontier detection for exploring unknown arenas
+mod frontier;
+/// Occupancy grid built from lidar scans
+mod grid;
+/// Home pose and return to start
+mod home;
 /// lidar module
 mod lidar;
 /// logger module
@@ -14,20 +36,48 @@ mod mongodb;
 mod nav;
 /// Odometer module for swarm algorithm
 mod odom;
+/// Operator commands
+mod operator;
+/// Latest frame capture and vision pipeline metrics
+mod pipeline;
+/// Grid based path planner
+mod planner;
 /// Publisher module
 mod publisher;
+/// MJPEG preview stream and saved debug frames
+mod preview;
+/// Metric range and bearing from bounding boxes
+mod ranging;
+/// Hot reload of the detection model
+mod reload;
+/// Visual servo controller for target approach
+mod servo;
+/// Versioned swarm message protocol
+mod swarm;
+/// AprilTag detection and pose
+mod tags;
+/// Multi object tracker
+mod tracker;
 /// yolo module
 mod yolo;
 
+use crate::coverage::SearchStrategy;
+use crate::grid::OccupancyGrid;
+use crate::home::ReturnReason;
 use crate::logger::LogEntry;
 use crate::odom::OdomData;
+use crate::operator::Command;
+use crate::reload::ReloadTrigger;
+use crate::tags::TagDetection;
+use crate::tracker::Track;
 use crate::yolo::load_model_file;
 
 use async_cell::sync::AsyncCell;
 use mongodb::MongoLogger;
 use r2r::QosProfile;
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
 
 type Num = f32;
 pub type XyXy = (Num, Num, Num, Num);
@@ -48,14 +98,39 @@ pub fn generate_node(name: &str) -> r2r::Result<Node> {
 pub enum Sequence {
     // Start randomly moving in x, y direction
     RandomMovement,
+    // Sweep the arena along a boustrophedon or spiral coverage path
+    CoverageSearch,
+    // Drive to the frontiers of the occupancy grid until nothing is unknown
+    Exploring,
     // If charm is located, start moving towards it
     TrackingToCharm,
-    // If swarm instruction is sent track to the swarm
-    Swarming(OdomData),
+    // If swarm instruction is sent track to the swarm, since when
+    Swarming(OdomData, Instant),
+    // Drive back to the pose the bot started at
+    ReturningHome(ReturnReason, Instant),
     // Stop
     Stop,
 }
 
+impl Sequence {
+    /// The search the bot runs while it has no target
+    pub fn search(strategy: SearchStrategy) -> Self {
+        match strategy {
+            SearchStrategy::Random => Sequence::RandomMovement,
+            SearchStrategy::Frontier => Sequence::Exploring,
+            _ => Sequence::CoverageSearch,
+        }
+    }
+
+    /// Searching, so free to answer a swarm call
+    pub fn is_searching(&self) -> bool {
+        matches!(
+            self,
+            Sequence::RandomMovement | Sequence::CoverageSearch | Sequence::Exploring
+        )
+    }
+}
+
 /// General log struct creator for easy mongodb logging
 /// [NOTE]: right now has mock values, needs to be updated to be realtime
 /// this is a great idea if done right !!!
@@ -68,7 +143,23 @@ async fn update_and_create_log_entry() -> LogEntry {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = load_model_file()?;
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("calibrate") => return Ok(calibration::run(&args[2..])?),
+        Some("evaluate") => return Ok(evaluation::run(&args[2..])?),
+        _ => {}
+    }
+
+    let mut config = load_model_file()?;
+    match camera::load_calibration(
+        &config.calibration_path,
+        config.camera.width,
+        config.camera.height,
+    ) {
+        Ok(intrinsics) => config.intrinsics = intrinsics,
+        Err(e) => println!("Using configured intrinsics, no calibration loaded: {}", e),
+    }
+
     // setup mongo logger
     // NOTE: IMPORTANT - formatting will probably need to change depending on actual layout of the
     // mongo server. this is being tested on my own atlas mongodb server.
@@ -95,18 +186,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let cell_odom = AsyncCell::shared();
     let weak_odom = cell_odom.take_weak();
+    // nav consumes the cell, the lidar process reads the pose without taking it
+    let (pose_tx, pose_rx) = watch::channel(None);
+
+    // occupancy grid filled by the lidar process, used by nav for planning
+    let grid = OccupancyGrid::shared(&config.map);
 
-    let (yolo_tx, yolo_rx) = mpsc::channel::<XyXy>(1000);
+    // nav only ever wants the newest tracks, so they go through a cell too
+    let cell_tracks = AsyncCell::<Vec<Track>>::shared();
+    let weak_tracks = cell_tracks.take_weak();
+
+    let (tag_tx, tag_rx) = mpsc::channel::<TagDetection>(100);
+    let (command_tx, command_rx) = mpsc::channel::<Command>(10);
+
+    // operator reload commands go straight to the vision stage
+    let reload = ReloadTrigger::default();
 
     let config_cl = config.clone();
+    let reload_cl = reload.clone();
     // camera process + yolo detect
     std::thread::spawn(move || {
-        let cam = camera::cam_plus_yolo_detect(yolo_tx, config_cl);
+        let cam = camera::cam_plus_yolo_detect(cell_tracks, tag_tx, config_cl, reload_cl);
 
         println!("{:?}", cam)
     });
 
     let lidar_cl = Arc::clone(&lidar_node);
+    let grid_cl = Arc::clone(&grid);
 
     // lidar process
     tokio::spawn(async move {
@@ -119,7 +225,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .expect("Subscribing to lidar should work")
         };
 
-        lidar::lidar_scan(&mut lidar_node_sub, cell_lidar).await;
+        lidar::lidar_scan(&mut lidar_node_sub, cell_lidar, pose_rx, grid_cl).await;
     });
 
     let cl = Arc::clone(&nav_node);
@@ -133,7 +239,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .expect("Subscribing to odom should work")
         };
 
-        odom::listen(&mut odom_node_sub, cell_odom).await;
+        odom::listen(&mut odom_node_sub, cell_odom, pose_tx).await;
+    });
+
+    let cl = Arc::clone(&nav_node);
+
+    // Operator command process
+    tokio::spawn(async move {
+        let mut operator_node_sub = {
+            let mut lock = cl.lock().expect("Locking the nav node should work");
+            let qos = QosProfile::default();
+            lock.subscribe("/operator", qos)
+                .expect("Subscribing to operator should work")
+        };
+
+        operator::listen(&mut operator_node_sub, command_tx, reload).await;
     });
 
     let cl = Arc::clone(&nav_node);
@@ -141,14 +261,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // navigation process
     tokio::spawn(async move {
         // this is what the bot is doing at any point in time
-        let start_sequence = Sequence::RandomMovement;
+        let start_sequence = Sequence::search(config_cl.coverage.strategy);
 
         let x = nav::move_process(
             start_sequence,
             cl,
             weak_lidar,
-            yolo_rx,
+            weak_tracks,
+            tag_rx,
             weak_odom,
+            command_rx,
+            grid,
             config_cl,
         )
         .await;
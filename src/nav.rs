@@ -2,58 +2,176 @@ use async_cell::sync::TakeWeak;
 use r2r::geometry_msgs::msg::{Twist, Vector3};
 use r2r::sensor_msgs::msg::LaserScan;
 use rand::distr::{Distribution, Uniform};
-use tokio::net::UdpSocket;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::Receiver;
-use tokio::time::{Duration, sleep};
+use tokio::time::{Duration, Instant, sleep};
 
+use crate::coverage::{CoverageMap, CoveragePlan};
 use crate::documenter;
+use crate::frontier;
+use crate::grid::SharedGrid;
+use crate::home::ReturnReason;
 use crate::lidar::{self};
 // use crate::logger::Logger;
 use crate::odom::OdomData;
+use crate::operator::Command;
+use crate::planner::{self, Waypoint};
+use crate::ranging;
+use crate::servo::VisualServo;
+use crate::swarm::{Envelope, Payload, SwarmSocket};
+use crate::tags::TagDetection;
+use crate::tracker::Track;
 use crate::yolo::ModelConfig;
-use crate::{Sequence, XyXy, publisher::TwistPublisher};
+use crate::{Sequence, publisher::TwistPublisher};
 
+use std::collections::{HashSet, VecDeque};
+use std::f64::consts::TAU;
 use std::io::Result;
 use std::sync::Arc;
 
+// switch to another track if the followed one has not been seen for this long
+const TRACK_LOST_AFTER: Duration = Duration::from_secs(1);
+// longest we wait for odometry while driving to a goal
+const GOAL_TICK: Duration = Duration::from_millis(100);
+
+/// Target track nav should act on, the followed one while it is not lost and
+/// the most confident one otherwise. Tracks from frames older than the
+/// configured result age are ignored
+fn pick_target<'a>(
+    tracks: &'a [Track],
+    followed: Option<(u64, Instant)>,
+    config: &ModelConfig,
+) -> Option<&'a Track> {
+    let max_age = config.vision.max_result_age();
+    let mut targets = tracks
+        .iter()
+        .filter(|t| config.is_target(&t.detection) && t.detection.frame.age() <= max_age);
+
+    match followed {
+        Some((id, last_seen)) if last_seen.elapsed() < TRACK_LOST_AFTER => {
+            targets.find(|t| t.id == id)
+        }
+        _ => targets.max_by(|a, b| a.detection.confidence.total_cmp(&b.detection.confidence)),
+    }
+}
+
 // main navigation logic
+#[allow(clippy::too_many_arguments)]
 pub async fn move_process(
     // sequence to start the nav move from
     starting_seq: Sequence,
     nav_node: crate::Node,
     lidar_rx: TakeWeak<LaserScan>,
-    mut yolo_rx: Receiver<XyXy>,
+    tracks_rx: TakeWeak<Vec<Track>>,
+    mut tag_rx: Receiver<TagDetection>,
     odom_rx: TakeWeak<OdomData>,
+    mut command_rx: Receiver<Command>,
+    grid: SharedGrid,
     config: ModelConfig,
     // logger: Logger,
 ) -> Result<()> {
     let publisher = TwistPublisher::new(nav_node.clone());
     let distance_step = Uniform::new(400, 500).expect("Failed to create distance step");
-    // listening for swarm data
-    let socket = UdpSocket::bind("0.0.0.0:8000").await?;
-    socket.connect(&config.addr[0]).await?;
-    let socket_arc = Arc::new(socket);
-    let s = socket_arc.clone();
     let sequence_mut = Arc::new(Mutex::new(starting_seq));
-    let mut buf = [0; 1024];
+    // listening for swarm data, without a swarm the bot searches alone
+    let swarm = match SwarmSocket::bind(&config.swarm, &config.addr).await {
+        Ok(swarm) => {
+            let swarm = Arc::new(swarm);
+            spawn_swarm(
+                Arc::clone(&swarm),
+                Arc::clone(&sequence_mut),
+                config.swarm.heartbeat_interval(),
+            );
+            Some(swarm)
+        }
+        Err(e) => {
+            eprintln!("Swarm disabled: {}", e);
+            None
+        }
+    };
+
+    // systematic search state, only used by Sequence::CoverageSearch
+    let mut coverage_plan = CoveragePlan::new(&config.coverage);
+    let mut coverage_map = CoverageMap::new(&config.coverage);
+    // path to the frontier we are exploring, only used by Sequence::Exploring
+    let mut exploration_path = VecDeque::new();
+    let mut seed_turns = 0;
+    // frontiers we already headed for, so one we can't clear is not picked forever
+    let mut blacklist = frontier::Blacklist::default();
+    // when we last told the swarm about the target we are tracking
+    let mut last_target_broadcast = None;
+    // path to the swarm target and the swarm call it was planned for
+    let mut swarm_path = VecDeque::new();
+    let mut swarm_leg = None;
+    // path home and the return it was planned for
+    let mut home_path = VecDeque::new();
+    let mut home_leg = None;
+
+    // the first pose we get after startup is home
+    let home = (&odom_rx).await;
+    if let Some(home) = home {
+        println!("Home pose recorded at ({:.2}, {:.2})", home.x1, home.y1);
+    }
+    let mission_start = Instant::now();
+    let mut mission_over = false;
+    // id of the track we are driving towards and when we last saw it
+    let mut followed: Option<(u64, Instant)> = None;
+    // (family, id) of every AprilTag seen so far
+    let mut seen_tags = HashSet::new();
+    // steers towards the followed track in Sequence::TrackingToCharm
+    let mut servo = VisualServo::new(&config.servo);
 
     loop {
-        let socket_arc = Arc::clone(&socket_arc);
-        let seq_cl = Arc::clone(&sequence_mut);
-
-        tokio::spawn(async move {
-            if let Ok(x) = socket_arc.try_recv(&mut buf) {
-                let slice = &buf[..x];
-                let json = serde_json::from_slice::<OdomData>(&slice);
-                if let Ok(json) = json {
-                    println!("json recived: {:?}", json);
-                    *seq_cl.lock().await = Sequence::Swarming(json);
+        let mut sequence = sequence_mut.lock().await;
+
+        if let Ok(command) = command_rx.try_recv() {
+            match command {
+                // already on the way, don't restart the return timeout
+                Command::ReturnHome if matches!(*sequence, Sequence::ReturningHome(..)) => {}
+                Command::ReturnHome => {
+                    mission_over = true;
+                    *sequence =
+                        Sequence::ReturningHome(ReturnReason::OperatorCommand, Instant::now());
                 }
+                Command::Stop => *sequence = Sequence::Stop,
+                // the operator listener hands reloads straight to the vision stage
+                Command::ReloadModel => {}
             }
-        });
+        }
 
-        let mut sequence = sequence_mut.lock().await;
+        while let Ok(tag) = tag_rx.try_recv() {
+            if seen_tags.insert((tag.family.clone(), tag.id)) {
+                match &tag.pose {
+                    Some(pose) => println!(
+                        "AprilTag {} {} at {:.2}m bearing {:.2}rad",
+                        tag.family, tag.id, pose.range_bearing.range, pose.range_bearing.bearing
+                    ),
+                    None => println!("AprilTag {} {} seen, no pose", tag.family, tag.id),
+                }
+                if let Some(pose) = &tag.pose {
+                    let [x, y, z] = pose.translation;
+                    println!(
+                        "    ({:.2}, {:.2}, {:.2}) from the camera, facing {:.2}rad",
+                        x,
+                        y,
+                        z,
+                        pose.facing()
+                    );
+                }
+                println!(
+                    "    decision margin {:.1}, {} bits corrected",
+                    tag.decision_margin, tag.hamming
+                );
+            }
+        }
+
+        if let Some(timeout) = config.home.mission_timeout_secs
+            && !mission_over
+            && mission_start.elapsed() >= Duration::from_secs(timeout)
+        {
+            mission_over = true;
+            *sequence = Sequence::ReturningHome(ReturnReason::MissionTimeout, Instant::now());
+        }
 
         match *sequence {
             Sequence::RandomMovement => {
@@ -85,70 +203,397 @@ pub async fn move_process(
                                 }
                             }
                     }
-                    // check yolo reciever
-                    yolo = yolo_rx.recv() => {
-                        if yolo.is_some() {
+                    // check the newest tracks
+                    tracks = &tracks_rx => {
+                        let target = tracks.as_deref().and_then(|t| pick_target(t, None, &config));
+                        if let Some(track) = target {
+                            println!(
+                                "Found {} ({:.2}) as track {}",
+                                track.detection.class_name, track.detection.confidence, track.id
+                            );
+                            followed = Some((track.id, Instant::now()));
                             *sequence = Sequence::TrackingToCharm;
                         }
                     }
                 }
             }
-            Sequence::TrackingToCharm => {
-                if let Some((x1, _, _, y2)) = yolo_rx.recv().await {
-                    if let Some(odom) = (&odom_rx).await {
-                        let string = serde_json::to_string(&odom).expect("convertion should work");
-                        let string = string.as_bytes();
-                        // communicate this fact to the bot1 and bot2
-                        s.send(&string).await;
-                        println!("Sending swarm info");
-                    }
+            Sequence::CoverageSearch => {
+                let Some(waypoint) = coverage_plan.current() else {
+                    println!(
+                        "Coverage search done, {:.1}% of the arena seen",
+                        coverage_map.percentage()
+                    );
+                    *sequence = Sequence::Stop;
+                    continue;
+                };
 
-                    if (200.0..=280.0).contains(&x1) {
-                        nav_stop(publisher.clone());
-
-                        if y2 < 485.0 {
-                            nav_move(10.0, 0.17, publisher.clone()).await;
-                        } else {
-                            println!("{}", "charm collected");
+                tokio::select! {
+                    odom = &odom_rx => {
+                        if let Some(odom) = odom {
+                            coverage_map.sweep(&odom);
+                            documenter::push_coverage(
+                                coverage_map.percentage(),
+                                coverage_map.seen_cells(),
+                                coverage_map.total_cells(),
+                            );
+
+                            let tolerance = config.planner.goal_tolerance;
+                            if drive_towards(&waypoint, &odom, publisher.clone(), tolerance) {
+                                println!("Coverage {:.1}%", coverage_map.percentage());
+                                coverage_plan.advance();
+                            }
+                        }
+                    }
+                    lidar = &lidar_rx => {
+                        if let Some(scan) = lidar {
+                            // something is in the way, back off and skip this waypoint
+                            if lidar::lidar_data(&scan).north {
+                                nav_move(10.0, -0.2, publisher.clone()).await;
+                                coverage_plan.advance();
+                            }
+                        }
+                    }
+                    tracks = &tracks_rx => {
+                        let target = tracks.as_deref().and_then(|t| pick_target(t, None, &config));
+                        if let Some(track) = target {
+                            println!(
+                                "Found {} ({:.2}) as track {}",
+                                track.detection.class_name, track.detection.confidence, track.id
+                            );
+                            followed = Some((track.id, Instant::now()));
+                            nav_stop(publisher.clone());
+                            *sequence = Sequence::TrackingToCharm;
                         }
-                    } else {
-                        let scaled = scale_0_to_200(x1);
-                        rotate(scaled as f64, publisher.clone(), None).await;
                     }
                 }
             }
-            Sequence::Swarming(odom_data) => {
-                let x1 = odom_data.x1;
-                let y1 = odom_data.y1;
+            Sequence::Exploring => {
+                let Some(&waypoint) = exploration_path.front() else {
+                    let Some(odom) = (&odom_rx).await else {
+                        continue;
+                    };
+
+                    // without a map there are no frontiers, which is not the same as none left
+                    let known = grid
+                        .lock()
+                        .expect("grid lock should not be poisoned")
+                        .known_cells();
+                    if known < config.exploration.min_known_cells {
+                        if seed_turns < config.exploration.seed_turns {
+                            seed_turns += 1;
+                            println!("Map still empty, turning in place to seed it");
+                            rotate_rad(TAU, publisher.clone()).await;
+                        } else {
+                            println!(
+                                "Map still empty after seeding, falling back to coverage search"
+                            );
+                            *sequence = Sequence::CoverageSearch;
+                        }
+                        continue;
+                    }
 
-                if let Some(odom) = (&odom_rx).await {
-                    let x2 = odom.x1;
-                    let y2 = odom.y1;
-                    let z = odom.z;
-                    let w = odom.w;
+                    let path = {
+                        let grid = grid.lock().expect("grid lock should not be poisoned");
+                        let pose = (odom.x1, odom.y1);
+                        let exploration = &config.exploration;
+
+                        let candidates: Vec<_> = frontier::find(&grid, pose, exploration)
+                            .into_iter()
+                            .filter(|f| !blacklist.is_blocked(f.centroid, exploration))
+                            .take(exploration.max_candidates)
+                            .collect();
+
+                        candidates.into_iter().find_map(|f| {
+                            // every pick counts, reaching a frontier that stays one too
+                            blacklist.record(f.centroid, exploration);
+                            planner::plan(&grid, pose, f.centroid, &config.planner)
+                                .filter(|path| !path.is_empty())
+                                .map(|path| (f, path))
+                        })
+                    };
+
+                    match path {
+                        Some((target, path)) => {
+                            println!(
+                                "Exploring frontier of {} cells (gain {}) at {:?}",
+                                target.size, target.gain, target.centroid
+                            );
+                            exploration_path = path.into();
+                        }
+                        None => {
+                            println!("No frontiers left we haven't given up on, exploration done");
+                            *sequence = Sequence::Stop;
+                        }
+                    }
 
-                    let result_x = x2 - x1;
-                    let result_y = y2 - y1;
+                    continue;
+                };
 
-                    let angle = result_y.atan2(result_x);
-                    let current_yaw = 2.0 * z.atan2(w);
+                tokio::select! {
+                    odom = &odom_rx => {
+                        if let Some(odom) = odom {
+                            let tolerance = config.planner.goal_tolerance;
+                            if drive_towards(&waypoint, &odom, publisher.clone(), tolerance) {
+                                exploration_path.pop_front();
+                            }
+                        }
+                    }
+                    lidar = &lidar_rx => {
+                        if let Some(scan) = lidar {
+                            // the map changed under us, back off and plan again
+                            if lidar::lidar_data(&scan).north {
+                                nav_move(10.0, -0.2, publisher.clone()).await;
+                                exploration_path.clear();
+                            }
+                        }
+                    }
+                    tracks = &tracks_rx => {
+                        let target = tracks.as_deref().and_then(|t| pick_target(t, None, &config));
+                        if let Some(track) = target {
+                            println!(
+                                "Found {} ({:.2}) as track {}",
+                                track.detection.class_name, track.detection.confidence, track.id
+                            );
+                            followed = Some((track.id, Instant::now()));
+                            nav_stop(publisher.clone());
+                            *sequence = Sequence::TrackingToCharm;
+                        }
+                    }
+                }
+            }
+            Sequence::TrackingToCharm => {
+                // stop rather than drive on with the last command once the target is gone
+                let Ok(tracks) = tokio::time::timeout(TRACK_LOST_AFTER, &tracks_rx).await else {
+                    nav_stop(publisher.clone());
+                    continue;
+                };
+
+                if let Some(tracks) = tracks {
+                    // stick with the track we are following until it is lost
+                    let Some(track) = pick_target(&tracks, followed, &config) else {
+                        nav_stop(publisher.clone());
+                        continue;
+                    };
+                    if followed.map(|(id, _)| id) != Some(track.id) {
+                        servo.reset();
+                    }
+                    followed = Some((track.id, Instant::now()));
+                    // how old the frame we are acting on is
+                    let latency = track.detection.frame.age();
+                    documenter::push_decision_latency(latency.as_secs_f64() * 1000.0);
 
-                    let angle_diff = normalize_angle(angle - current_yaw);
+                    let estimate =
+                        ranging::estimate(&track.detection, &config.intrinsics, &config.ranging);
 
-                    println!("{:?}", angle_diff);
+                    if let Some(odom) = (&odom_rx).await {
+                        // where the target is if we can range it, where we are otherwise
+                        let (x, y) = match estimate {
+                            Some(target) => {
+                                let heading = odom.yaw() + target.bearing;
+                                (
+                                    odom.x1 + target.range * heading.cos(),
+                                    odom.y1 + target.range * heading.sin(),
+                                )
+                            }
+                            None => (odom.x1, odom.y1),
+                        };
+                        // communicate this fact to the rest of the swarm, not on every frame
+                        let due = last_target_broadcast.is_none_or(|sent: Instant| {
+                            sent.elapsed() >= config.swarm.target_interval()
+                        });
+                        if let (true, Some(swarm)) = (due, &swarm) {
+                            last_target_broadcast = Some(Instant::now());
+                            swarm
+                                .broadcast(Payload::TargetFound {
+                                    class_name: track.detection.class_name.clone(),
+                                    x,
+                                    y,
+                                })
+                                .await;
+                            println!("Sending swarm info");
+                        }
+                    }
+                    if let Some(target) = estimate {
+                        println!(
+                            "{} at {:.2}m, {:.2}rad in frame {}",
+                            track.detection.class_name,
+                            target.range,
+                            target.bearing,
+                            track.detection.frame.id
+                        );
+                    }
 
-                    if angle_diff.abs() < 0.05 {
+                    let command =
+                        servo.update(track.bbox, &track.detection.frame, &config.intrinsics);
+                    // the metric range beats the box size when the class has one.
+                    // a box cut off at the bottom is closer than we can range
+                    let (x1, _, x2, _) = track.bbox;
+                    let stop_distance = config
+                        .ranging
+                        .stop_distance(&config.intrinsics, track.detection.frame.height);
+                    let (range, bearing) = match estimate {
+                        Some(target) => (target.range, target.bearing),
+                        None if ranging::clipped_at_bottom(&track.detection, &config.ranging) => (
+                            0.0,
+                            ranging::bearing(f64::from(x1 + x2) / 2.0, &config.intrinsics),
+                        ),
+                        None => (f64::INFINITY, 0.0),
+                    };
+                    let collected = command.arrived
+                        || (range <= stop_distance
+                            && bearing.abs() <= config.ranging.bearing_tolerance);
+
+                    if collected {
                         nav_stop(publisher.clone());
+                        println!("{}", "charm collected");
+
+                        if config.home.return_after_collect && !mission_over {
+                            mission_over = true;
+                            *sequence = Sequence::ReturningHome(
+                                ReturnReason::CharmCollected,
+                                Instant::now(),
+                            );
+                        }
                     } else {
-                        if angle_diff > 0.0 {
-                            rotate(1.0, publisher.clone(), None).await;
-                        } else {
-                            rotate(-1.0, publisher.clone(), None).await;
+                        nav_velocity(command.linear, command.angular, publisher.clone());
+                    }
+                }
+            }
+            Sequence::Swarming(target, started) => {
+                if started.elapsed() >= config.planner.goal_timeout() {
+                    println!("Swarm target not reached in time, searching again");
+                    nav_stop(publisher.clone());
+                    *sequence = Sequence::search(config.coverage.strategy);
+                    continue;
+                }
+
+                // plan once per swarm call, and again after backing off an obstacle
+                if swarm_leg != Some(started) {
+                    let Some(odom) = (&odom_rx).await else {
+                        continue;
+                    };
+                    let path = {
+                        let grid = grid.lock().expect("grid lock should not be poisoned");
+                        planner::plan(
+                            &grid,
+                            (odom.x1, odom.y1),
+                            (target.x1, target.y1),
+                            &config.planner,
+                        )
+                    };
+
+                    match path {
+                        Some(waypoints) => {
+                            println!("Planned {} waypoints to swarm target", waypoints.len());
+                            swarm_path = waypoints.into();
+                            swarm_leg = Some(started);
+                        }
+                        None => {
+                            // no path through the grid, at least face the target
+                            println!("No path to swarm target, turning towards it");
+                            face_point(target.x1, target.y1, &odom, publisher.clone()).await;
+                            *sequence = Sequence::search(config.coverage.strategy);
                         }
                     }
+                    continue;
+                }
+
+                if swarm_path.is_empty() {
+                    // the target should be in view now, searching picks it up
+                    nav_stop(publisher.clone());
+                    *sequence = Sequence::search(config.coverage.strategy);
+                    continue;
+                }
+
+                // one control step per iteration so the sequence lock is released in between
+                let tolerance = config.planner.goal_tolerance;
+                if !follow_path(
+                    &mut swarm_path,
+                    &odom_rx,
+                    &lidar_rx,
+                    publisher.clone(),
+                    tolerance,
+                )
+                .await
+                {
+                    swarm_leg = None;
+                }
+            }
+            Sequence::ReturningHome(reason, started) => {
+                let Some(home) = home else {
+                    println!("No home pose recorded, stopping instead");
+                    *sequence = Sequence::Stop;
+                    continue;
+                };
+
+                if started.elapsed() >= config.home.return_timeout() {
+                    let message = format!(
+                        "Not home after {:.1}s, stopping",
+                        started.elapsed().as_secs_f64()
+                    );
+                    println!("{}", message);
+                    documenter::push_operation("ReturningHome", message);
+                    nav_stop(publisher.clone());
+                    *sequence = Sequence::Stop;
+                    continue;
+                }
+
+                // plan when we start heading home, and again after backing off an obstacle
+                if home_leg != Some(started) {
+                    let Some(odom) = (&odom_rx).await else {
+                        continue;
+                    };
+                    let message = format!(
+                        "Returning home ({}) from ({:.2}, {:.2}) to ({:.2}, {:.2})",
+                        reason, odom.x1, odom.y1, home.x1, home.y1
+                    );
+                    println!("{}", message);
+                    documenter::push_operation("ReturningHome", message);
+
+                    let path = {
+                        let grid = grid.lock().expect("grid lock should not be poisoned");
+                        planner::plan(
+                            &grid,
+                            (odom.x1, odom.y1),
+                            (home.x1, home.y1),
+                            &config.planner,
+                        )
+                    };
+                    // straight line home if the grid has no path for us
+                    let waypoints = path.unwrap_or_else(|| {
+                        vec![Waypoint {
+                            x: home.x1,
+                            y: home.y1,
+                        }]
+                    });
+                    home_path = waypoints.into();
+                    home_leg = Some(started);
+                    continue;
                 }
 
-                *sequence = Sequence::Stop;
+                if home_path.is_empty() {
+                    let message =
+                        format!("Arrived home after {:.1}s", started.elapsed().as_secs_f64());
+                    println!("{}", message);
+                    documenter::push_operation("ReturningHome", message);
+                    nav_stop(publisher.clone());
+                    *sequence = Sequence::Stop;
+                    continue;
+                }
+
+                // step-wise like swarming, so operator commands preempt the way home
+                let tolerance = config.planner.goal_tolerance;
+                if !follow_path(
+                    &mut home_path,
+                    &odom_rx,
+                    &lidar_rx,
+                    publisher.clone(),
+                    tolerance,
+                )
+                .await
+                {
+                    home_leg = None;
+                }
             }
             Sequence::Stop => {
                 nav_stop(publisher.clone());
@@ -263,6 +708,179 @@ pub async fn rotate_rad(x: f64, publisher: TwistPublisher) {
     nav_stop(publisher);
 }
 
+/// Answer swarm messages and keep telling the swarm we are alive
+fn spawn_swarm(
+    swarm: Arc<SwarmSocket>,
+    sequence_mut: Arc<Mutex<Sequence>>,
+    heartbeat_interval: Duration,
+) {
+    let swarm_cl = Arc::clone(&swarm);
+    tokio::spawn(async move {
+        loop {
+            let (from, decoded) = match swarm_cl.recv().await {
+                Ok(received) => received,
+                Err(e) => {
+                    eprintln!("Swarm socket failed: {}", e);
+                    return;
+                }
+            };
+
+            match decoded {
+                // liveness is tracked by the socket itself
+                Ok(Envelope {
+                    payload: Payload::Heartbeat | Payload::Announce { .. },
+                    ..
+                }) => {}
+                Ok(envelope) => {
+                    println!("Swarm {:?} from {}", envelope.payload, envelope.sender);
+                    if let Payload::TargetFound { x, y, .. } = envelope.payload {
+                        let target = OdomData {
+                            x1: x,
+                            y1: y,
+                            z: 0.0,
+                            w: 1.0,
+                        };
+                        // never drop a target of our own or an operator stop for it
+                        let mut sequence = sequence_mut.lock().await;
+                        if sequence.is_searching() {
+                            *sequence = Sequence::Swarming(target, Instant::now());
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Dropped swarm message from {}: {}", from, e),
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            swarm.heartbeat().await;
+            sleep(heartbeat_interval).await;
+        }
+    });
+}
+
+// turn a little towards a point in the odom frame
+async fn face_point(x: f64, y: f64, odom: &OdomData, publisher: TwistPublisher) {
+    let angle = (y - odom.y1).atan2(x - odom.x1);
+    let angle_diff = normalize_angle(angle - odom.yaw());
+
+    println!("{:?}", angle_diff);
+
+    if angle_diff.abs() < 0.05 {
+        nav_stop(publisher);
+    } else if angle_diff > 0.0 {
+        rotate(1.0, publisher, None).await;
+    } else {
+        rotate(-1.0, publisher, None).await;
+    }
+}
+
+/// One control step along a planned path, drops waypoints as we reach them.
+/// Returns false if something was in the way and we backed off, the path has
+/// to be planned again then
+async fn follow_path(
+    path: &mut VecDeque<Waypoint>,
+    odom_rx: &TakeWeak<OdomData>,
+    lidar_rx: &TakeWeak<LaserScan>,
+    publisher: TwistPublisher,
+    tolerance: f64,
+) -> bool {
+    let Some(&waypoint) = path.front() else {
+        return true;
+    };
+
+    tokio::select! {
+        odom = odom_rx => {
+            if let Some(odom) = odom
+                && drive_towards(&waypoint, &odom, publisher.clone(), tolerance)
+            {
+                path.pop_front();
+            }
+        }
+        lidar = lidar_rx => {
+            if let Some(scan) = lidar
+                && lidar::lidar_data(&scan).north
+            {
+                nav_move(10.0, -0.2, publisher).await;
+                return false;
+            }
+        }
+        // wake up to check the timeouts even if odometry stalls
+        _ = sleep(GOAL_TICK) => {}
+    }
+
+    true
+}
+
+/// Publish one control step towards a waypoint, returns true once we are
+/// within `tolerance` of it
+pub fn drive_towards(
+    waypoint: &Waypoint,
+    odom: &OdomData,
+    publisher: TwistPublisher,
+    tolerance: f64,
+) -> bool {
+    let max_linear = 0.15;
+    let max_angular = 1.0;
+
+    let dx = waypoint.x - odom.x1;
+    let dy = waypoint.y - odom.y1;
+    let distance = dx.hypot(dy);
+
+    if distance < tolerance {
+        return true;
+    }
+
+    let heading_error = normalize_angle(dy.atan2(dx) - odom.yaw());
+    let angular = (1.5 * heading_error).clamp(-max_angular, max_angular);
+    // only drive forward once we are roughly facing the waypoint
+    let linear = if heading_error.abs() < 0.4 {
+        (0.5 * distance).min(max_linear)
+    } else {
+        0.0
+    };
+
+    let twist = Twist {
+        linear: Vector3 {
+            x: linear,
+            y: 0.0,
+            z: 0.0,
+        },
+        angular: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: angular,
+        },
+    };
+
+    if let Err(e) = publisher.publish(&twist) {
+        eprintln!("Failed to publish go to pose twist: {}", e);
+    }
+
+    false
+}
+
+/// Publish a velocity and return right away, the next call replaces it
+pub fn nav_velocity(linear: f64, angular: f64, publisher: TwistPublisher) {
+    let twist = Twist {
+        linear: Vector3 {
+            x: linear,
+            y: 0.0,
+            z: 0.0,
+        },
+        angular: Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: angular,
+        },
+    };
+
+    if let Err(e) = publisher.publish(&twist) {
+        eprintln!("Failed to publish servo twist: {}", e);
+    }
+}
+
 pub fn nav_stop(publisher: TwistPublisher) {
     let twist = Twist {
         linear: Vector3 {
@@ -284,23 +902,13 @@ pub fn nav_stop(publisher: TwistPublisher) {
     };
 }
 
-fn scale_0_to_200(value: f32) -> f32 {
-    let new_min = 0.5;
-    let new_max = -0.5;
-    let old_min = 0.0;
-    let old_max = 500.0;
-
-    let normalized = (value - old_min) / (old_max - old_min);
-    new_min + normalized * (new_max - new_min)
-}
-
 fn quaternion_to_yaw(x: f64, y: f64, z: f64, w: f64) -> f64 {
     let siny_cosp = 2.0 * (w * z + x * y);
     let cosy_cosp = 1.0 - 2.0 * (y * y + z * z);
     siny_cosp.atan2(cosy_cosp)
 }
 
-fn normalize_angle(angle: f64) -> f64 {
+pub fn normalize_angle(angle: f64) -> f64 {
     let mut a = angle;
     while a > std::f64::consts::PI {
         a -= 2.0 * std::f64::consts::PI;
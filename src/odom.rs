@@ -5,6 +5,7 @@ use r2r::nav_msgs::msg::Odometry;
 use serde::{Deserialize, Serialize};
 
 use std::sync::Arc;
+use tokio::sync::watch;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct OdomData {
@@ -14,9 +15,17 @@ pub struct OdomData {
     pub w: f64,
 }
 
+impl OdomData {
+    /// heading of the bot, odom only ever rotates around z
+    pub fn yaw(&self) -> f64 {
+        2.0 * self.z.atan2(self.w)
+    }
+}
+
 pub async fn listen<T: Stream<Item = Odometry> + Unpin>(
     mut stream: T,
     tx: Arc<AsyncCell<OdomData>>,
+    pose_tx: watch::Sender<Option<OdomData>>,
 ) {
     loop {
         match stream.next().await {
@@ -32,6 +41,8 @@ pub async fn listen<T: Stream<Item = Odometry> + Unpin>(
                 let data = OdomData { x1, y1, z, w };
 
                 tx.set(data);
+                // nav takes from the cell, everyone else reads the pose here
+                pose_tx.send_replace(Some(data));
             }
             _ => (),
         }
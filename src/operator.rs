@@ -0,0 +1,47 @@
+use futures::Stream;
+use futures::stream::StreamExt;
+use r2r::std_msgs::msg::String as StringMsg;
+use tokio::sync::mpsc::Sender;
+
+use crate::reload::ReloadTrigger;
+
+/// Instructions an operator can send to the bot over the `/operator` topic,
+/// e.g. `ros2 topic pub --once /operator std_msgs/msg/String "data: home"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    ReturnHome,
+    Stop,
+    /// load the model and thresholds from disk again, handled by the vision stage
+    ReloadModel,
+}
+
+impl Command {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "home" | "return_home" => Some(Command::ReturnHome),
+            "stop" => Some(Command::Stop),
+            "reload" | "reload_model" => Some(Command::ReloadModel),
+            _ => None,
+        }
+    }
+}
+
+pub async fn listen<T: Stream<Item = StringMsg> + Unpin>(
+    mut stream: T,
+    tx: Sender<Command>,
+    reload: ReloadTrigger,
+) {
+    while let Some(msg) = stream.next().await {
+        match Command::parse(&msg.data) {
+            Some(Command::ReloadModel) => {
+                println!("Operator command: {:?}", Command::ReloadModel);
+                reload.request();
+            }
+            Some(command) => {
+                println!("Operator command: {:?}", command);
+                let _ = tx.send(command).await;
+            }
+            None => eprintln!("Unknown operator command: {}", msg.data),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+use serde::Deserialize;
+
+use crate::camera::CameraConfig;
+use crate::documenter;
+use crate::error::Error;
+use crate::frame_source::{self, Grabbed, SourceConfig};
+
+// give up on a frame source after this many failed reads in a row
+const MAX_CAPTURE_ERRORS: u32 = 50;
+// wait between failed reads, grows with every error up to the max
+const CAPTURE_BACKOFF: Duration = Duration::from_millis(20);
+const MAX_CAPTURE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Vision pipeline section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VisionConfig {
+    /// nav ignores tracks whose frame was captured longer ago than this
+    pub max_result_age_ms: u64,
+    /// seconds between the fps and latency lines in the log
+    pub report_interval_secs: f64,
+}
+
+impl Default for VisionConfig {
+    fn default() -> Self {
+        Self {
+            max_result_age_ms: 500,
+            report_interval_secs: 10.0,
+        }
+    }
+}
+
+impl VisionConfig {
+    pub fn max_result_age(&self) -> Duration {
+        Duration::from_millis(self.max_result_age_ms)
+    }
+}
+
+/// Where and when a frame came from, every detection found in it carries a copy
+#[derive(Debug, Clone, Copy)]
+pub struct FrameInfo {
+    /// counts up by one for every captured frame, gaps are dropped frames
+    pub id: u64,
+    /// monotonic capture time, before decoding, for ageing results out
+    pub captured: Instant,
+    pub width: u32,
+    pub height: u32,
+    /// how long detection took on this frame, zero until it has run
+    pub inference: Duration,
+}
+
+impl FrameInfo {
+    /// A frame captured right now
+    pub fn new(id: u64, image: &DynamicImage) -> Self {
+        Self::captured_at(id, image, Instant::now())
+    }
+
+    /// A frame the source got hold of at `captured`
+    pub fn captured_at(id: u64, image: &DynamicImage, captured: Instant) -> Self {
+        Self {
+            id,
+            captured,
+            width: image.width(),
+            height: image.height(),
+            inference: Duration::ZERO,
+        }
+    }
+
+    /// time since capture
+    pub fn age(&self) -> Duration {
+        self.captured.elapsed()
+    }
+}
+
+/// A frame and where it came from
+pub struct CapturedFrame {
+    pub image: DynamicImage,
+    pub info: FrameInfo,
+}
+
+#[derive(Default)]
+struct SlotState {
+    frame: Option<CapturedFrame>,
+    /// frames overwritten before inference got to them
+    dropped: u64,
+    finished: bool,
+}
+
+/// Holds only the newest frame, capture overwrites whatever inference has not taken yet
+#[derive(Default)]
+pub struct FrameSlot {
+    state: Mutex<SlotState>,
+    ready: Condvar,
+}
+
+impl FrameSlot {
+    fn put(&self, frame: CapturedFrame) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("frame slot lock should not be poisoned");
+        if state.frame.replace(frame).is_some() {
+            state.dropped += 1;
+        }
+        self.ready.notify_one();
+    }
+
+    fn finish(&self) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("frame slot lock should not be poisoned");
+        state.finished = true;
+        self.ready.notify_one();
+    }
+
+    /// Wait for the newest frame, None once the source is exhausted
+    pub fn take(&self) -> Option<CapturedFrame> {
+        let state = self
+            .state
+            .lock()
+            .expect("frame slot lock should not be poisoned");
+        let mut state = self
+            .ready
+            .wait_while(state, |state| state.frame.is_none() && !state.finished)
+            .expect("frame slot lock should not be poisoned");
+
+        state.frame.take()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.state
+            .lock()
+            .map(|state| state.dropped)
+            .unwrap_or_default()
+    }
+}
+
+/// Run the frame source on its own thread so capture never waits for inference
+pub fn spawn_capture(
+    source: &SourceConfig,
+    camera: &CameraConfig,
+) -> Result<Arc<FrameSlot>, Error> {
+    let slot = Arc::new(FrameSlot::default());
+    let slot_cl = Arc::clone(&slot);
+    let (source, camera) = (source.clone(), camera.clone());
+    // the camera handle can't move between threads, so it is opened in the capture thread
+    let (opened_tx, opened_rx) = std_mpsc::channel();
+
+    thread::spawn(move || {
+        let mut source = match frame_source::open(&source, &camera) {
+            Ok(source) => {
+                let _ = opened_tx.send(Ok(()));
+                source
+            }
+            Err(e) => {
+                let _ = opened_tx.send(Err(e.to_string()));
+                slot_cl.finish();
+                return;
+            }
+        };
+        let mut rate = RateCounter::default();
+        let mut next_id = 0;
+        let mut errors = 0;
+
+        loop {
+            match source.next_frame() {
+                Ok(Some(Grabbed { image, captured })) => {
+                    errors = 0;
+                    let info = FrameInfo::captured_at(next_id, &image, captured);
+                    next_id += 1;
+                    documenter::push_capture_fps(rate.tick(info.captured));
+                    slot_cl.put(CapturedFrame { image, info });
+                }
+                Ok(None) => {
+                    println!("Frame source finished");
+                    slot_cl.finish();
+                    return;
+                }
+                // a dropped camera frame is not worth stopping for, a gone camera is
+                Err(e) => {
+                    errors += 1;
+                    eprintln!("Failed to read a frame ({} in a row): {}", errors, e);
+                    if errors >= MAX_CAPTURE_ERRORS {
+                        eprintln!("Frame source keeps failing, stopping capture");
+                        slot_cl.finish();
+                        return;
+                    }
+                    thread::sleep((CAPTURE_BACKOFF * errors).min(MAX_CAPTURE_BACKOFF));
+                }
+            }
+        }
+    });
+
+    match opened_rx.recv() {
+        Ok(Ok(())) => Ok(slot),
+        Ok(Err(e)) => Err(anyhow::anyhow!("Failed to open frame source: {}", e).into()),
+        Err(_) => Err(anyhow::anyhow!("Frame source thread died while opening").into()),
+    }
+}
+
+/// Events per second, smoothed over the last events
+#[derive(Default)]
+pub struct RateCounter {
+    last: Option<Instant>,
+    rate: f64,
+}
+
+impl RateCounter {
+    /// Record an event and return the smoothed rate
+    pub fn tick(&mut self, now: Instant) -> f64 {
+        if let Some(last) = self.last.replace(now) {
+            let secs = (now - last).as_secs_f64();
+            if secs > 0.0 {
+                // exponential moving average, roughly the last ten frames
+                let rate = 1.0 / secs;
+                self.rate = if self.rate == 0.0 {
+                    rate
+                } else {
+                    0.9 * self.rate + 0.1 * rate
+                };
+            }
+        }
+
+        self.rate
+    }
+}
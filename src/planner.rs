@@ -0,0 +1,350 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::grid::{CellState, OccupancyGrid};
+
+// integer move costs so the open set can live in a BinaryHeap
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+const NEIGHBOURS: [(i64, i64, u32); 8] = [
+    (1, 0, STRAIGHT_COST),
+    (-1, 0, STRAIGHT_COST),
+    (0, 1, STRAIGHT_COST),
+    (0, -1, STRAIGHT_COST),
+    (1, 1, DIAGONAL_COST),
+    (1, -1, DIAGONAL_COST),
+    (-1, 1, DIAGONAL_COST),
+    (-1, -1, DIAGONAL_COST),
+];
+
+/// Search used to find the path through the grid
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    AStar,
+    Dijkstra,
+}
+
+/// Planner section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PlannerConfig {
+    pub algorithm: Algorithm,
+    /// radius of the bot, obstacles are grown by this much before planning
+    pub robot_radius: f64,
+    /// how close (meters) we need to get to a waypoint before moving on
+    pub goal_tolerance: f64,
+    /// seconds to reach a planned goal before giving up on it
+    pub goal_timeout_secs: f64,
+}
+
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::AStar,
+            robot_radius: 0.12,
+            goal_tolerance: 0.1,
+            goal_timeout_secs: 30.0,
+        }
+    }
+}
+
+impl PlannerConfig {
+    pub fn goal_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.goal_timeout_secs.max(0.0))
+    }
+}
+
+/// A point in the odom frame the bot should drive through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Mark every cell within `radius` meters of an occupied cell as blocked
+pub fn inflate(grid: &OccupancyGrid, radius: f64) -> Vec<bool> {
+    let mut blocked = vec![false; grid.width * grid.height];
+    let r = (radius / grid.resolution).ceil() as i64;
+
+    for cy in 0..grid.height {
+        for cx in 0..grid.width {
+            if grid.state(cx, cy) != CellState::Occupied {
+                continue;
+            }
+
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    if dx * dx + dy * dy > r * r {
+                        continue;
+                    }
+
+                    if let Some((nx, ny)) = offset(grid, cx, cy, dx, dy) {
+                        blocked[grid.index(nx, ny)] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    blocked
+}
+
+/// Plan a collision free path between two world positions, unknown cells are
+/// treated as free so we can plan through parts of the arena we have not seen
+pub fn plan(
+    grid: &OccupancyGrid,
+    start: (f64, f64),
+    goal: (f64, f64),
+    config: &PlannerConfig,
+) -> Option<Vec<Waypoint>> {
+    let blocked = inflate(grid, config.robot_radius);
+
+    let start = grid.world_to_cell(start.0, start.1)?;
+    let goal = grid.world_to_cell(goal.0, goal.1)?;
+    // the goal is usually another bot, which shows up as an obstacle itself
+    let goal = nearest_free(grid, &blocked, goal)?;
+
+    let cells = search(grid, &blocked, start, goal, config.algorithm)?;
+
+    Some(simplify(grid, &cells))
+}
+
+fn search(
+    grid: &OccupancyGrid,
+    blocked: &[bool],
+    start: (usize, usize),
+    goal: (usize, usize),
+    algorithm: Algorithm,
+) -> Option<Vec<(usize, usize)>> {
+    let heuristic = |cx: usize, cy: usize| match algorithm {
+        Algorithm::Dijkstra => 0,
+        Algorithm::AStar => {
+            let dx = cx.abs_diff(goal.0) as u32;
+            let dy = cy.abs_diff(goal.1) as u32;
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        }
+    };
+
+    let mut cost = vec![u32::MAX; grid.width * grid.height];
+    let mut parent = vec![usize::MAX; grid.width * grid.height];
+    let mut open = BinaryHeap::new();
+
+    let start_idx = grid.index(start.0, start.1);
+    let goal_idx = grid.index(goal.0, goal.1);
+    cost[start_idx] = 0;
+    open.push(Reverse((heuristic(start.0, start.1), start_idx)));
+
+    while let Some(Reverse((_, idx))) = open.pop() {
+        if idx == goal_idx {
+            break;
+        }
+
+        let (cx, cy) = (idx % grid.width, idx / grid.width);
+
+        for (dx, dy, step) in NEIGHBOURS {
+            let Some((nx, ny)) = offset(grid, cx, cy, dx, dy) else {
+                continue;
+            };
+
+            let next = grid.index(nx, ny);
+            // the start cell is allowed to be inside the inflated area,
+            // otherwise a bot next to a wall could never leave it
+            if blocked[next] && next != start_idx {
+                continue;
+            }
+
+            let new_cost = cost[idx] + step;
+            if new_cost < cost[next] {
+                cost[next] = new_cost;
+                parent[next] = idx;
+                open.push(Reverse((new_cost + heuristic(nx, ny), next)));
+            }
+        }
+    }
+
+    if cost[goal_idx] == u32::MAX {
+        return None;
+    }
+
+    let mut path = vec![goal];
+    let mut idx = goal_idx;
+    while idx != start_idx {
+        idx = parent[idx];
+        path.push((idx % grid.width, idx / grid.width));
+    }
+    path.reverse();
+
+    Some(path)
+}
+
+// breadth first search for the closest cell that is not blocked
+fn nearest_free(
+    grid: &OccupancyGrid,
+    blocked: &[bool],
+    cell: (usize, usize),
+) -> Option<(usize, usize)> {
+    let mut seen = vec![false; grid.width * grid.height];
+    let mut queue = VecDeque::from([cell]);
+    seen[grid.index(cell.0, cell.1)] = true;
+
+    while let Some((cx, cy)) = queue.pop_front() {
+        if !blocked[grid.index(cx, cy)] {
+            return Some((cx, cy));
+        }
+
+        for (dx, dy, _) in NEIGHBOURS {
+            if let Some((nx, ny)) = offset(grid, cx, cy, dx, dy) {
+                let idx = grid.index(nx, ny);
+                if !seen[idx] {
+                    seen[idx] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// only keep the cells where the path changes direction
+fn simplify(grid: &OccupancyGrid, cells: &[(usize, usize)]) -> Vec<Waypoint> {
+    let mut waypoints = Vec::new();
+
+    for (i, &(cx, cy)) in cells.iter().enumerate().skip(1) {
+        let is_last = i == cells.len() - 1;
+        let turns = !is_last && {
+            let (px, py) = cells[i - 1];
+            let (nx, ny) = cells[i + 1];
+            (cx as i64 - px as i64, cy as i64 - py as i64)
+                != (nx as i64 - cx as i64, ny as i64 - cy as i64)
+        };
+
+        if turns || is_last {
+            let (x, y) = grid.cell_to_world(cx, cy);
+            waypoints.push(Waypoint { x, y });
+        }
+    }
+
+    waypoints
+}
+
+fn offset(grid: &OccupancyGrid, cx: usize, cy: usize, dx: i64, dy: i64) -> Option<(usize, usize)> {
+    let nx = cx as i64 + dx;
+    let ny = cy as i64 + dy;
+
+    if nx < 0 || ny < 0 || nx >= grid.width as i64 || ny >= grid.height as i64 {
+        return None;
+    }
+
+    Some((nx as usize, ny as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::MapConfig;
+
+    // 20x20 cells of 0.1m, the world origin in the bottom left corner
+    fn grid() -> OccupancyGrid {
+        OccupancyGrid::new(&MapConfig {
+            resolution: 0.1,
+            width: 20,
+            height: 20,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            max_range: 3.5,
+        })
+    }
+
+    fn path_cost(path: &[(usize, usize)]) -> u32 {
+        path.windows(2)
+            .map(|pair| {
+                let dx = pair[0].0.abs_diff(pair[1].0);
+                let dy = pair[0].1.abs_diff(pair[1].1);
+                assert!(dx <= 1 && dy <= 1, "path jumps between {:?}", pair);
+                if dx + dy == 2 {
+                    DIAGONAL_COST
+                } else {
+                    STRAIGHT_COST
+                }
+            })
+            .sum()
+    }
+
+    // a wall along x = 10 with a single gap at the top
+    fn wall_with_gap(grid: &OccupancyGrid) -> Vec<bool> {
+        let mut blocked = vec![false; grid.width * grid.height];
+        for cy in 0..grid.height - 1 {
+            blocked[grid.index(10, cy)] = true;
+        }
+        blocked
+    }
+
+    #[test]
+    fn straight_line_on_an_empty_grid() {
+        let grid = grid();
+        let blocked = vec![false; grid.width * grid.height];
+
+        let path = search(&grid, &blocked, (0, 5), (9, 5), Algorithm::AStar).unwrap();
+
+        assert_eq!(path.first(), Some(&(0, 5)));
+        assert_eq!(path.last(), Some(&(9, 5)));
+        assert_eq!(path_cost(&path), 9 * STRAIGHT_COST);
+    }
+
+    #[test]
+    fn goes_around_a_wall_through_the_gap() {
+        let grid = grid();
+        let blocked = wall_with_gap(&grid);
+
+        let path = search(&grid, &blocked, (5, 0), (15, 0), Algorithm::AStar).unwrap();
+
+        assert!(path.iter().all(|&(cx, cy)| !blocked[grid.index(cx, cy)]));
+        assert!(path.contains(&(10, 19)));
+    }
+
+    #[test]
+    fn astar_finds_paths_as_short_as_dijkstra() {
+        let grid = grid();
+        let blocked = wall_with_gap(&grid);
+
+        let astar = search(&grid, &blocked, (2, 3), (17, 4), Algorithm::AStar).unwrap();
+        let dijkstra = search(&grid, &blocked, (2, 3), (17, 4), Algorithm::Dijkstra).unwrap();
+
+        assert_eq!(path_cost(&astar), path_cost(&dijkstra));
+    }
+
+    #[test]
+    fn no_path_through_a_closed_wall() {
+        let grid = grid();
+        let mut blocked = wall_with_gap(&grid);
+        blocked[grid.index(10, 19)] = true;
+
+        assert!(search(&grid, &blocked, (5, 5), (15, 5), Algorithm::AStar).is_none());
+    }
+
+    #[test]
+    fn plan_ends_on_the_goal_cell() {
+        let grid = grid();
+        let config = PlannerConfig::default();
+
+        let waypoints = plan(&grid, (0.05, 0.05), (1.25, 0.05), &config).unwrap();
+
+        // a straight path needs no waypoint but the last one
+        assert_eq!(waypoints.len(), 1);
+        let last = waypoints[0];
+        assert!((last.x - 1.25).abs() < 1e-9 && (last.y - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn plan_outside_the_grid_fails() {
+        let grid = grid();
+
+        assert!(plan(&grid, (0.5, 0.5), (5.0, 5.0), &PlannerConfig::default()).is_none());
+    }
+}
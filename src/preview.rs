@@ -0,0 +1,197 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+use image::codecs::jpeg::JpegEncoder;
+use serde::Deserialize;
+
+use crate::annotate::{self, Overlay};
+use crate::error::Error;
+
+/// Debug preview section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PreviewConfig {
+    pub enabled: bool,
+    /// serve the annotated frames as an MJPEG stream on this address
+    pub http_addr: Option<String>,
+    /// save annotated frames into this directory
+    pub save_dir: Option<String>,
+    /// frames per second sent to the stream
+    pub stream_fps: f64,
+    /// seconds between saved frames
+    pub save_interval_secs: f64,
+    pub jpeg_quality: u8,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            http_addr: Some("0.0.0.0:8081".to_string()),
+            save_dir: None,
+            stream_fps: 10.0,
+            save_interval_secs: 1.0,
+            jpeg_quality: 70,
+        }
+    }
+}
+
+/// Last encoded frame, stream clients wait on `updated` for the next one
+#[derive(Default)]
+struct LatestFrame {
+    // sequence number and jpeg bytes
+    frame: Mutex<(u64, Arc<Vec<u8>>)>,
+    updated: Condvar,
+}
+
+/// Annotates frames and hands them to the stream and the disk at their own rates
+pub struct Preview {
+    config: PreviewConfig,
+    latest: Option<Arc<LatestFrame>>,
+    save_dir: Option<PathBuf>,
+    last_stream: Option<Instant>,
+    last_save: Option<Instant>,
+    saved: u64,
+}
+
+impl Preview {
+    pub fn new(config: &PreviewConfig) -> Result<Self, Error> {
+        if !(config.stream_fps > 0.0 && config.stream_fps.is_finite()) {
+            return Err(anyhow::anyhow!(
+                "Preview stream_fps must be above zero, got {}",
+                config.stream_fps
+            )
+            .into());
+        }
+
+        let latest = match &config.http_addr {
+            Some(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                let latest = Arc::new(LatestFrame::default());
+                let latest_cl = Arc::clone(&latest);
+                thread::spawn(move || serve(listener, latest_cl));
+                println!("Preview stream on http://{}", addr);
+                Some(latest)
+            }
+            None => None,
+        };
+
+        let save_dir = config.save_dir.as_ref().map(PathBuf::from);
+        if let Some(dir) = &save_dir {
+            fs::create_dir_all(dir)?;
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            latest,
+            save_dir,
+            last_stream: None,
+            last_save: None,
+            saved: 0,
+        })
+    }
+
+    fn stream_due(&self, now: Instant) -> bool {
+        self.latest.is_some() && due(self.last_stream, now, 1.0 / self.config.stream_fps)
+    }
+
+    fn save_due(&self, now: Instant) -> bool {
+        self.save_dir.is_some() && due(self.last_save, now, self.config.save_interval_secs)
+    }
+
+    /// Annotate and publish a frame if the stream or the disk wants one
+    pub fn publish(&mut self, img: &DynamicImage, overlay: &Overlay) {
+        let now = Instant::now();
+        let (stream, save) = (self.stream_due(now), self.save_due(now));
+        if !stream && !save {
+            return;
+        }
+
+        let mut annotated = img.to_rgb8();
+        annotate::draw(&mut annotated, overlay);
+
+        let mut jpeg = Vec::new();
+        let encoder = JpegEncoder::new_with_quality(&mut jpeg, self.config.jpeg_quality);
+        if let Err(e) = annotated.write_with_encoder(encoder) {
+            eprintln!("Failed to encode preview frame: {}", e);
+            return;
+        }
+
+        if let (true, Some(latest)) = (stream, &self.latest) {
+            self.last_stream = Some(now);
+            let mut frame = latest
+                .frame
+                .lock()
+                .expect("preview lock should not be poisoned");
+            *frame = (frame.0 + 1, Arc::new(jpeg.clone()));
+            latest.updated.notify_all();
+        }
+
+        if let (true, Some(dir)) = (save, &self.save_dir) {
+            self.last_save = Some(now);
+            let path = dir.join(format!("frame_{:06}.jpg", self.saved));
+            self.saved += 1;
+
+            if let Err(e) = fs::write(&path, &jpeg) {
+                eprintln!("Failed to save {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn due(last: Option<Instant>, now: Instant, interval_secs: f64) -> bool {
+    last.is_none_or(|last| now - last >= Duration::from_secs_f64(interval_secs.max(0.0)))
+}
+
+fn serve(listener: TcpListener, latest: Arc<LatestFrame>) {
+    for stream in listener.incoming().flatten() {
+        let latest = Arc::clone(&latest);
+        thread::spawn(move || {
+            // the client is gone once a write fails, nothing else to do
+            let _ = stream_client(stream, &latest);
+        });
+    }
+}
+
+fn stream_client(mut stream: TcpStream, latest: &LatestFrame) -> std::io::Result<()> {
+    // whatever was requested, everyone gets the stream
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request)?;
+
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Type: multipart/x-mixed-replace; boundary=frame\r\n\
+          Cache-Control: no-cache\r\n\
+          Connection: close\r\n\r\n",
+    )?;
+
+    let mut seen = 0;
+    loop {
+        let jpeg = {
+            let guard = latest
+                .frame
+                .lock()
+                .expect("preview lock should not be poisoned");
+            let guard = latest
+                .updated
+                .wait_while(guard, |frame| frame.0 == seen)
+                .expect("preview lock should not be poisoned");
+            seen = guard.0;
+            Arc::clone(&guard.1)
+        };
+
+        write!(
+            stream,
+            "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            jpeg.len()
+        )?;
+        stream.write_all(&jpeg)?;
+        stream.write_all(b"\r\n")?;
+    }
+}
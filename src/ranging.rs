@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::camera::CameraIntrinsics;
+use crate::yolo::Detection;
+
+/// Ranging section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RangingConfig {
+    /// height of the camera lens above the floor, meters
+    pub camera_height: f64,
+    /// downward tilt of the camera, radians
+    pub camera_pitch: f64,
+    /// how far in front of base_link the camera sits, meters
+    pub camera_offset_x: f64,
+    /// real world height of every class we know the size of, meters
+    pub object_heights: HashMap<String, f64>,
+    /// stop approaching once the target is this close to base_link, meters.
+    /// Never below the closest range the camera can still see the floor at
+    pub stop_distance: f64,
+    /// boxes ending this close to the bottom of the frame are cut off, pixels
+    pub clip_margin: f64,
+    /// turn towards the target until the bearing is within this, radians
+    pub bearing_tolerance: f64,
+}
+
+impl Default for RangingConfig {
+    fn default() -> Self {
+        let object_heights = [
+            ("football", 0.22),
+            ("green cone", 0.15),
+            ("purple cone", 0.15),
+            ("red cone", 0.15),
+            ("yellow cone", 0.15),
+        ]
+        .into_iter()
+        .map(|(name, height)| (name.to_string(), height))
+        .collect();
+
+        Self {
+            camera_height: 0.12,
+            camera_pitch: 0.0,
+            camera_offset_x: 0.05,
+            object_heights,
+            stop_distance: 0.15,
+            clip_margin: 2.0,
+            bearing_tolerance: 0.1,
+        }
+    }
+}
+
+/// Where a detected object is relative to base_link
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeBearing {
+    /// meters
+    pub range: f64,
+    /// radians, positive to the left like every other ROS angle
+    pub bearing: f64,
+}
+
+impl RangingConfig {
+    /// Distance to stop approaching at for frames this many pixels high
+    pub fn stop_distance(&self, intrinsics: &CameraIntrinsics, image_height: u32) -> f64 {
+        self.stop_distance
+            .max(min_visible_range(intrinsics, self, image_height))
+    }
+}
+
+/// Closest range in front of base_link where the floor is still in the frame.
+/// Anything nearer has its bottom edge cut off and can't be ranged
+pub fn min_visible_range(
+    intrinsics: &CameraIntrinsics,
+    config: &RangingConfig,
+    image_height: u32,
+) -> f64 {
+    let (_, bottom) = intrinsics.undistort(intrinsics.cx, image_height as f64);
+    let below_horizon = ((bottom - intrinsics.cy) / intrinsics.fy).atan() + config.camera_pitch;
+
+    if below_horizon <= 0.01 {
+        // the floor is never in view, nothing can be ranged from it
+        return f64::INFINITY;
+    }
+
+    config.camera_height / below_horizon.tan() + config.camera_offset_x
+}
+
+/// Whether the box runs into the bottom of the frame, so the object is closer
+/// than we can range it
+pub fn clipped_at_bottom(detection: &Detection, config: &RangingConfig) -> bool {
+    let (_, _, _, y2) = detection.bbox;
+
+    y2 as f64 >= detection.frame.height as f64 - config.clip_margin
+}
+
+/// Direction of an image column relative to the camera axis, radians, positive
+/// to the left
+pub fn bearing(u: f64, intrinsics: &CameraIntrinsics) -> f64 {
+    let (u, _) = intrinsics.undistort(u, intrinsics.cy);
+
+    -((u - intrinsics.cx) / intrinsics.fx).atan()
+}
+
+/// Estimate the distance and direction of a detection from its bounding box.
+/// Uses the bottom edge of the box on the floor plane and the known height of
+/// the class, averaging the two when both are available. With a segmentation
+/// mask the floor contact point of the mask replaces the box bottom. Boxes cut
+/// off by the bottom of the frame are too close to range and give None
+pub fn estimate(
+    detection: &Detection,
+    intrinsics: &CameraIntrinsics,
+    config: &RangingConfig,
+) -> Option<RangeBearing> {
+    if clipped_at_bottom(detection, config) {
+        return None;
+    }
+
+    let (x1, y1, x2, y2) = detection.bbox;
+    // lens distortion bends rays near the edges of the image
+    let (x1, y1) = intrinsics.undistort(x1 as f64, y1 as f64);
+    let (x2, y2) = intrinsics.undistort(x2 as f64, y2 as f64);
+
+    // a box bottom is only on the floor for objects that fill their box,
+    // the mask knows where the object really touches it
+    let (contact_u, contact_v) = detection
+        .mask
+        .as_ref()
+        .and_then(|mask| mask.contact_point())
+        .map(|(u, v)| intrinsics.undistort(u as f64, v as f64))
+        .unwrap_or(((x1 + x2) / 2.0, y2));
+
+    // angle below the horizon of the ray through the floor contact point
+    let below_horizon = ((contact_v - intrinsics.cy) / intrinsics.fy).atan() + config.camera_pitch;
+    let ground = (below_horizon > 0.01).then(|| config.camera_height / below_horizon.tan());
+
+    let pixel_height = y2 - y1;
+    let size = config
+        .object_heights
+        .get(&detection.class_name)
+        .filter(|_| pixel_height > 1.0)
+        .map(|height| intrinsics.fy * height / pixel_height);
+
+    let forward = match (ground, size) {
+        (Some(ground), Some(size)) => (ground + size) / 2.0,
+        (Some(distance), None) | (None, Some(distance)) => distance,
+        (None, None) => return None,
+    };
+
+    // image x grows to the right, ROS y grows to the left
+    let lateral = -forward * (contact_u - intrinsics.cx) / intrinsics.fx;
+    let x = forward + config.camera_offset_x;
+
+    Some(RangeBearing {
+        range: x.hypot(lateral),
+        bearing: lateral.atan2(x),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::FrameInfo;
+    use image::DynamicImage;
+
+    // no distortion and a round focal length so the expected ranges are easy to work out
+    fn intrinsics() -> CameraIntrinsics {
+        CameraIntrinsics {
+            fx: 500.0,
+            fy: 500.0,
+            cx: 320.0,
+            cy: 240.0,
+            k1: 0.0,
+            k2: 0.0,
+            k3: 0.0,
+            p1: 0.0,
+            p2: 0.0,
+        }
+    }
+
+    fn detection(class_name: &str, bbox: (f32, f32, f32, f32)) -> Detection {
+        Detection {
+            bbox,
+            class_id: 0,
+            class_name: class_name.to_string(),
+            confidence: 0.9,
+            frame: FrameInfo::new(0, &DynamicImage::new_rgb8(640, 480)),
+            mask: None,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn ground_plane_range() {
+        // bottom edge 50px below the horizon: 0.12m / tan(atan(0.1)) = 1.2m, plus the camera offset
+        let target = estimate(
+            &detection("box", (300.0, 250.0, 340.0, 290.0)),
+            &intrinsics(),
+            &RangingConfig::default(),
+        )
+        .unwrap();
+
+        assert_close(target.range, 1.25);
+        assert_close(target.bearing, 0.0);
+    }
+
+    #[test]
+    fn ground_plane_bearing_is_positive_to_the_left() {
+        // centred 100px right of cx: lateral = -1.2 * 100 / 500
+        let right = estimate(
+            &detection("box", (400.0, 250.0, 440.0, 290.0)),
+            &intrinsics(),
+            &RangingConfig::default(),
+        )
+        .unwrap();
+
+        assert_close(right.range, 1.25_f64.hypot(0.24));
+        assert_close(right.bearing, (-0.24_f64).atan2(1.25));
+    }
+
+    #[test]
+    fn known_height_range() {
+        // a 0.22m football 110px high is 500 * 0.22 / 110 = 1m away. The box
+        // ends above the horizon, so the floor gives no estimate
+        let target = estimate(
+            &detection("football", (300.0, 100.0, 340.0, 210.0)),
+            &intrinsics(),
+            &RangingConfig::default(),
+        )
+        .unwrap();
+
+        assert_close(target.range, 1.05);
+    }
+
+    #[test]
+    fn both_estimates_are_averaged() {
+        // ground 1.2m and size 1m
+        let target = estimate(
+            &detection("football", (300.0, 180.0, 340.0, 290.0)),
+            &intrinsics(),
+            &RangingConfig::default(),
+        )
+        .unwrap();
+
+        assert_close(target.range, 1.15);
+    }
+
+    #[test]
+    fn box_above_the_horizon_has_no_range() {
+        let target = estimate(
+            &detection("box", (300.0, 150.0, 340.0, 200.0)),
+            &intrinsics(),
+            &RangingConfig::default(),
+        );
+
+        assert_eq!(target, None);
+    }
+
+    #[test]
+    fn box_clipped_at_the_bottom_has_no_range() {
+        let clipped = detection("football", (300.0, 380.0, 340.0, 479.0));
+
+        assert!(clipped_at_bottom(&clipped, &RangingConfig::default()));
+        assert_eq!(
+            estimate(&clipped, &intrinsics(), &RangingConfig::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn stop_distance_is_never_inside_the_blind_spot() {
+        let config = RangingConfig::default();
+
+        // the bottom row is 240px below the horizon: 0.12 / 0.48 + 0.05
+        assert_close(min_visible_range(&intrinsics(), &config, 480), 0.3);
+        assert_close(config.stop_distance(&intrinsics(), 480), 0.3);
+    }
+}
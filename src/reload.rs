@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self as std_mpsc, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Deserialize;
+
+use crate::detector::Detector;
+use crate::error::Error;
+use crate::yolo::{self, ModelConfig};
+
+/// Hot reload section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReloadConfig {
+    /// reload when the model or the config file changes on disk
+    pub watch: bool,
+    /// seconds between checks of the file modification times
+    pub poll_interval_secs: f64,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: true,
+            poll_interval_secs: 2.0,
+        }
+    }
+}
+
+/// Asks the vision stage to reload its detector, shared with the operator listener
+#[derive(Debug, Clone, Default)]
+pub struct ReloadTrigger(Arc<AtomicBool>);
+
+impl ReloadTrigger {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Loads a new detector in the background whenever the model or the config
+/// changes, or a reload is requested, so the vision loop never waits for it
+pub struct Reloader {
+    config: ReloadConfig,
+    trigger: ReloadTrigger,
+    /// files we watch and their modification time when last loaded
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+    loading: Option<Receiver<Result<(Detector, String), Error>>>,
+}
+
+impl Reloader {
+    pub fn new(model_config: &ModelConfig, trigger: ReloadTrigger) -> Self {
+        Self {
+            config: model_config.reload.clone(),
+            trigger,
+            watched: watch_list(&model_config.model_path),
+            last_poll: Instant::now(),
+            loading: None,
+        }
+    }
+
+    fn changed(&mut self) -> bool {
+        let interval = Duration::from_secs_f64(self.config.poll_interval_secs.max(0.0));
+        if !self.config.watch || self.last_poll.elapsed() < interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        self.watched
+            .iter()
+            .any(|(path, loaded)| modified(path) != *loaded)
+    }
+
+    /// Called once per frame, hands out the new detector once it has loaded
+    pub fn poll(&mut self) -> Option<Detector> {
+        if let Some(loading) = &self.loading {
+            return match loading.try_recv() {
+                Ok(Ok((detector, model_path))) => {
+                    self.loading = None;
+                    // the new config may point at another model file
+                    if !self
+                        .watched
+                        .iter()
+                        .any(|(path, _)| path == Path::new(&model_path))
+                    {
+                        self.watched = watch_list(&model_path);
+                    }
+                    println!("Reloaded the detector from {}", model_path);
+                    Some(detector)
+                }
+                Ok(Err(e)) => {
+                    self.loading = None;
+                    // the old detector keeps running, we try again on the next change
+                    eprintln!("Failed to reload the detector: {}", e);
+                    None
+                }
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    self.loading = None;
+                    eprintln!("Detector reload thread stopped without a result");
+                    None
+                }
+            };
+        }
+
+        if self.trigger.take() || self.changed() {
+            println!("Loading a new detector in the background");
+            // changes made while we load trigger another reload
+            for (path, loaded) in &mut self.watched {
+                *loaded = modified(path);
+            }
+
+            let (loaded_tx, loaded_rx) = std_mpsc::channel();
+            thread::spawn(move || {
+                // no color fallback here, a bad model keeps the current detector running
+                let loaded = yolo::load_model_file().and_then(|config| {
+                    Detector::configured(&config).map(|detector| (detector, config.model_path))
+                });
+                let _ = loaded_tx.send(loaded);
+            });
+            self.loading = Some(loaded_rx);
+        }
+
+        None
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn watch_list(model_path: &str) -> Vec<(PathBuf, Option<SystemTime>)> {
+    [PathBuf::from(yolo::CONFIG_PATH), PathBuf::from(model_path)]
+        .into_iter()
+        .map(|path| {
+            let time = modified(&path);
+            (path, time)
+        })
+        .collect()
+}
@@ -0,0 +1,257 @@
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use crate::XyXy;
+use crate::camera::CameraIntrinsics;
+use crate::pipeline::FrameInfo;
+
+/// Visual servo section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServoConfig {
+    /// PID gains on the normalized horizontal error, -1 at the left edge of
+    /// the image and 1 at the right
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    /// the integral of the error never grows past this, so a target held off
+    /// center doesn't wind it up
+    pub max_integral: f64,
+    /// horizontal errors smaller than this count as centered
+    pub angular_deadband: f64,
+    /// rad/s
+    pub max_angular: f64,
+    /// m/s while the target is still small in the frame
+    pub max_linear: f64,
+    /// the target is reached once its box is this fraction of the image height
+    pub stop_size: f64,
+    /// stop driving forward once the box is within this fraction of stop_size
+    pub linear_deadband: f64,
+    /// don't drive forward while the horizontal error is larger than this
+    pub drive_error: f64,
+}
+
+impl Default for ServoConfig {
+    fn default() -> Self {
+        Self {
+            kp: 1.2,
+            ki: 0.0,
+            kd: 0.1,
+            max_integral: 0.5,
+            angular_deadband: 0.05,
+            max_angular: 0.8,
+            max_linear: 0.17,
+            stop_size: 0.45,
+            linear_deadband: 0.05,
+            drive_error: 0.5,
+        }
+    }
+}
+
+impl ServoConfig {
+    /// Pixel range around the optical center the controller treats as centered,
+    /// for frames `width` pixels wide
+    pub fn centered_window(&self, intrinsics: &CameraIntrinsics, width: u32) -> (f32, f32) {
+        let half = width as f64 / 2.0 * self.angular_deadband;
+        ((intrinsics.cx - half) as f32, (intrinsics.cx + half) as f32)
+    }
+}
+
+/// Velocities for one frame
+#[derive(Debug, Clone, Copy)]
+pub struct ServoCommand {
+    /// m/s, forward
+    pub linear: f64,
+    /// rad/s, positive to the left like every other ROS angle
+    pub angular: f64,
+    /// the target is centered and as large as it gets before we stop
+    pub arrived: bool,
+}
+
+/// Image based visual servo, steers a box to the image center and drives at
+/// it until it fills `stop_size` of the frame
+pub struct VisualServo {
+    config: ServoConfig,
+    /// capture time and error of the previous frame
+    last: Option<(Instant, f64)>,
+    integral: f64,
+}
+
+impl VisualServo {
+    pub fn new(config: &ServoConfig) -> Self {
+        Self {
+            config: config.clone(),
+            last: None,
+            integral: 0.0,
+        }
+    }
+
+    /// Forget the previous target, call when switching to a new one
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.integral = 0.0;
+    }
+
+    /// Command for a box found in `frame`. The box is steered to the optical
+    /// center, errors and sizes are relative to the frame size
+    pub fn update(
+        &mut self,
+        (x1, y1, x2, y2): XyXy,
+        frame: &FrameInfo,
+        intrinsics: &CameraIntrinsics,
+    ) -> ServoCommand {
+        let config = &self.config;
+        let captured = frame.captured;
+        let half_width = (frame.width as f64 / 2.0).max(1.0);
+        let center_u = (x1 + x2) as f64 / 2.0;
+        let error = ((center_u - intrinsics.cx) / half_width).clamp(-1.0, 1.0);
+        let size = (y2 - y1) as f64 / (frame.height as f64).max(1.0);
+
+        let centered = error.abs() < config.angular_deadband;
+        let angular = if centered {
+            // nothing to correct, don't let the integral wind up either
+            self.integral = 0.0;
+            0.0
+        } else {
+            let dt = self
+                .last
+                .map(|(last, _)| captured.saturating_duration_since(last).as_secs_f64())
+                .filter(|dt| *dt > 0.0);
+            let derivative = match (dt, self.last) {
+                (Some(dt), Some((_, last_error))) => {
+                    self.integral = (self.integral + error * dt)
+                        .clamp(-config.max_integral, config.max_integral);
+                    (error - last_error) / dt
+                }
+                _ => 0.0,
+            };
+
+            // a box right of center means turning right, which is negative
+            let output = config.kp * error + config.ki * self.integral + config.kd * derivative;
+            (-output).clamp(-config.max_angular, config.max_angular)
+        };
+        self.last = Some((captured, error));
+
+        // slow down as the target grows, stop once it is big enough
+        let remaining = ((config.stop_size - size) / config.stop_size).clamp(0.0, 1.0);
+        let close = remaining < config.linear_deadband;
+        let linear = if close || error.abs() > config.drive_error {
+            0.0
+        } else {
+            config.max_linear * remaining * (1.0 - error.abs() / config.drive_error)
+        };
+
+        ServoCommand {
+            linear,
+            angular,
+            arrived: centered && close,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+    use std::time::Duration;
+
+    fn frame(captured: Instant) -> FrameInfo {
+        FrameInfo::captured_at(0, &DynamicImage::new_rgb8(640, 480), captured)
+    }
+
+    // a 40px high box centered on column u
+    fn at_column(u: f32) -> XyXy {
+        (u - 20.0, 200.0, u + 20.0, 240.0)
+    }
+
+    #[test]
+    fn error_inside_deadband_gives_no_turn() {
+        let mut servo = VisualServo::new(&ServoConfig::default());
+        let intrinsics = CameraIntrinsics::default();
+        let start = Instant::now();
+
+        // 5px off center is 5 / 320 of the half width, inside the 0.05 deadband
+        for n in 0..3 {
+            let at = start + Duration::from_millis(100 * n);
+            let command = servo.update(at_column(325.0), &frame(at), &intrinsics);
+            assert_eq!(command.angular, 0.0);
+            assert!(command.linear > 0.0);
+        }
+        assert_eq!(servo.integral, 0.0);
+    }
+
+    #[test]
+    fn turn_saturates_at_max_angular() {
+        let config = ServoConfig {
+            kp: 10.0,
+            ..ServoConfig::default()
+        };
+        let intrinsics = CameraIntrinsics::default();
+        let now = Instant::now();
+
+        // target on the right edge turns right, negative
+        let right = VisualServo::new(&config).update(at_column(630.0), &frame(now), &intrinsics);
+        assert_eq!(right.angular, -config.max_angular);
+        // too far off center to drive forward
+        assert_eq!(right.linear, 0.0);
+
+        let left = VisualServo::new(&config).update(at_column(10.0), &frame(now), &intrinsics);
+        assert_eq!(left.angular, config.max_angular);
+    }
+
+    #[test]
+    fn speed_saturates_at_max_linear() {
+        let config = ServoConfig::default();
+        let intrinsics = CameraIntrinsics::default();
+        // a tiny centered box, as far away as it gets
+        let command = VisualServo::new(&config).update(
+            (319.0, 240.0, 321.0, 240.0),
+            &frame(Instant::now()),
+            &intrinsics,
+        );
+
+        assert_eq!(command.linear, config.max_linear);
+        assert!(!command.arrived);
+    }
+
+    #[test]
+    fn integral_is_clamped() {
+        let config = ServoConfig {
+            kp: 0.0,
+            ki: 1.0,
+            kd: 0.0,
+            max_integral: 0.3,
+            ..ServoConfig::default()
+        };
+        let mut servo = VisualServo::new(&config);
+        let intrinsics = CameraIntrinsics::default();
+        let start = Instant::now();
+
+        // the target held half way to the right edge for ten seconds
+        let mut command = None;
+        for n in 0..=10 {
+            let at = start + Duration::from_secs(n);
+            command = Some(servo.update(at_column(480.0), &frame(at), &intrinsics));
+        }
+
+        assert_eq!(servo.integral, config.max_integral);
+        assert!((command.unwrap().angular + config.ki * config.max_integral).abs() < 1e-9);
+    }
+
+    #[test]
+    fn large_centered_box_has_arrived() {
+        let config = ServoConfig::default();
+        let intrinsics = CameraIntrinsics::default();
+        // 240px of 480 is past the 0.45 stop size
+        let command = VisualServo::new(&config).update(
+            (280.0, 200.0, 360.0, 440.0),
+            &frame(Instant::now()),
+            &intrinsics,
+        );
+
+        assert!(command.arrived);
+        assert_eq!(command.linear, 0.0);
+        assert_eq!(command.angular, 0.0);
+    }
+}
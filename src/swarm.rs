@@ -0,0 +1,812 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::net::{UdpSocket, lookup_host};
+
+use crate::documenter;
+use crate::logger::SwarmPeer;
+
+/// Version of the envelope and payloads below, bump on incompatible changes
+pub const PROTOCOL_VERSION: u32 = 2;
+
+type HmacSha256 = Hmac<Sha256>;
+/// hex encoded HMAC-SHA256 appended to every signed message
+const MAC_LEN: usize = 64;
+
+/// Swarm section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SwarmConfig {
+    /// name this bot signs its messages with, unique within the swarm. Falls
+    /// back to the SWARM_BOT_ID environment variable, the swarm does not start
+    /// without one
+    pub bot_id: Option<String>,
+    /// local address swarm messages are sent from and received on
+    pub bind_addr: String,
+    /// shared HMAC key of the swarm, falls back to the SWARM_SECRET_KEY
+    /// environment variable. The swarm does not start without one
+    #[serde(alias = "my_secret_key")]
+    pub secret_key: Option<String>,
+    /// messages sent longer ago than this are rejected as replays, seconds
+    pub max_message_age_secs: f64,
+    /// announce this bot on the multicast group and add the bots heard there as peers
+    pub discovery: bool,
+    /// IPv4 multicast group and port of the announcements
+    pub multicast_group: String,
+    /// seconds between announcements, or heartbeats to static peers without discovery
+    pub heartbeat_interval_secs: f64,
+    /// a peer is stale once this many heartbeats in a row were missed
+    pub missed_heartbeats: u32,
+    /// what this bot can do, sent along with its announcements
+    pub capabilities: Vec<String>,
+    /// seconds between repeats of the target we are tracking
+    pub target_interval_secs: f64,
+}
+
+impl Default for SwarmConfig {
+    fn default() -> Self {
+        Self {
+            bot_id: None,
+            bind_addr: "0.0.0.0:8000".to_string(),
+            secret_key: None,
+            max_message_age_secs: 30.0,
+            discovery: true,
+            multicast_group: "239.255.42.99:8010".to_string(),
+            heartbeat_interval_secs: 1.0,
+            missed_heartbeats: 3,
+            capabilities: vec!["detect".to_string(), "collect".to_string()],
+            target_interval_secs: 2.0,
+        }
+    }
+}
+
+impl SwarmConfig {
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.heartbeat_interval_secs.max(0.1))
+    }
+
+    pub fn target_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.target_interval_secs.max(0.0))
+    }
+
+    /// How long a peer may stay silent before it is stale
+    pub fn stale_after(&self) -> Duration {
+        self.heartbeat_interval() * self.missed_heartbeats.max(1)
+    }
+}
+
+/// What a swarm message is about. Positions are in the odom frame, meters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Payload {
+    /// a target was seen here, come help
+    TargetFound { class_name: String, x: f64, y: f64 },
+    /// where the sender is right now
+    PoseUpdate { x: f64, y: f64, yaw: f64 },
+    /// the sender is alive
+    Heartbeat,
+    /// sent to the multicast group, the sender is alive and reachable at the
+    /// address the announcement came from
+    Announce { capabilities: Vec<String> },
+    /// the sender is going for the target here, others should pick another one
+    Claim { x: f64, y: f64 },
+    /// the sender gave up on or collected the target here
+    Release { x: f64, y: f64 },
+}
+
+/// Every swarm message on the wire
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u32,
+    /// bot id of the sender
+    pub sender: String,
+    /// random for every run of the sender, tells our own looped back messages
+    /// apart from another bot that was given our id
+    pub instance: u64,
+    /// counts up by one for every message a bot sends
+    pub sequence: u64,
+    /// milliseconds since the unix epoch when the message was sent
+    pub timestamp_ms: u64,
+    pub payload: Payload,
+}
+
+/// Why a datagram could not be turned into an envelope
+#[derive(Debug)]
+pub enum DecodeError {
+    /// missing or wrong HMAC, not from a bot that knows the key
+    Unauthenticated,
+    /// sent by a bot speaking another protocol version
+    UnsupportedVersion(u32),
+    /// right version, but not a message we understand
+    Malformed(serde_json::Error),
+    /// older than the newest message we already have from the sender, or too
+    /// old altogether
+    Replayed,
+    /// another bot sends with our bot id
+    DuplicateBotId,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Unauthenticated => write!(f, "missing or invalid HMAC"),
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported protocol version {}, we speak {}",
+                version, PROTOCOL_VERSION
+            ),
+            DecodeError::Malformed(e) => write!(f, "malformed message: {}", e),
+            DecodeError::Replayed => write!(f, "replayed or stale message"),
+            DecodeError::DuplicateBotId => write!(f, "another bot uses our bot_id"),
+        }
+    }
+}
+
+/// Just enough of an envelope to decide whether we can read the rest
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+fn parse(bytes: &[u8]) -> Result<Envelope, DecodeError> {
+    let Version { version } = serde_json::from_slice(bytes).map_err(DecodeError::Malformed)?;
+
+    if version != PROTOCOL_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    serde_json::from_slice(bytes).map_err(DecodeError::Malformed)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Messages seen by a codec, by outcome
+#[derive(Debug, Default)]
+pub struct SwarmStats {
+    pub accepted: AtomicU64,
+    pub unauthenticated: AtomicU64,
+    pub unsupported_version: AtomicU64,
+    pub malformed: AtomicU64,
+    pub replayed: AtomicU64,
+    pub duplicate_id: AtomicU64,
+}
+
+impl SwarmStats {
+    pub fn rejected(&self) -> u64 {
+        self.unauthenticated.load(Ordering::Relaxed)
+            + self.unsupported_version.load(Ordering::Relaxed)
+            + self.malformed.load(Ordering::Relaxed)
+            + self.replayed.load(Ordering::Relaxed)
+            + self.duplicate_id.load(Ordering::Relaxed)
+    }
+}
+
+/// Wraps payloads of this bot into signed envelopes and checks the ones we receive
+pub struct Codec {
+    bot_id: String,
+    instance: u64,
+    sequence: AtomicU64,
+    /// keyed once, cloned for every message
+    mac: HmacSha256,
+    max_age_ms: u64,
+    /// timestamp and sequence of the newest message from every sender
+    newest: Mutex<HashMap<String, (u64, u64)>>,
+    pub stats: SwarmStats,
+}
+
+impl Codec {
+    /// Fails without a bot id or a key, unauthenticated swarm traffic is never
+    /// accepted
+    pub fn new(config: &SwarmConfig) -> io::Result<Self> {
+        let bot_id = config
+            .bot_id
+            .clone()
+            .or_else(|| env::var("SWARM_BOT_ID").ok())
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no swarm bot_id configured and SWARM_BOT_ID is not set",
+                )
+            })?;
+        let key = config
+            .secret_key
+            .clone()
+            .or_else(|| env::var("SWARM_SECRET_KEY").ok())
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no swarm secret_key configured and SWARM_SECRET_KEY is not set",
+                )
+            })?;
+        let mac =
+            HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+
+        Ok(Self {
+            bot_id,
+            instance: rand::random(),
+            sequence: AtomicU64::new(0),
+            mac,
+            max_age_ms: (config.max_message_age_secs.max(0.0) * 1000.0) as u64,
+            newest: Mutex::new(HashMap::new()),
+            stats: SwarmStats::default(),
+        })
+    }
+
+    pub fn encode(&self, payload: Payload) -> Vec<u8> {
+        let envelope = Envelope {
+            version: PROTOCOL_VERSION,
+            sender: self.bot_id.clone(),
+            instance: self.instance,
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+            timestamp_ms: now_ms(),
+            payload,
+        };
+
+        let mut bytes = serde_json::to_vec(&envelope).expect("envelopes always serialize");
+        let mut mac = self.mac.clone();
+        mac.update(&bytes);
+        bytes.extend(hex::encode(mac.finalize().into_bytes()).as_bytes());
+
+        bytes
+    }
+
+    /// Check the HMAC of a datagram, parse it and make sure it is not a
+    /// replay, counting the outcome. None for messages we sent ourselves,
+    /// which multicast loops back to us
+    pub fn decode(&self, bytes: &[u8]) -> Option<Result<Envelope, DecodeError>> {
+        let opened = self.verify(bytes).and_then(parse);
+        if let Ok(envelope) = &opened
+            && envelope.instance == self.instance
+        {
+            return None;
+        }
+
+        let decoded = opened
+            .and_then(|envelope| self.check_sender(envelope))
+            .and_then(|envelope| self.check_fresh(envelope));
+
+        let counter = match &decoded {
+            Ok(_) => &self.stats.accepted,
+            Err(DecodeError::Unauthenticated) => &self.stats.unauthenticated,
+            Err(DecodeError::UnsupportedVersion(_)) => &self.stats.unsupported_version,
+            Err(DecodeError::Malformed(_)) => &self.stats.malformed,
+            Err(DecodeError::Replayed) => &self.stats.replayed,
+            Err(DecodeError::DuplicateBotId) => &self.stats.duplicate_id,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        Some(decoded)
+    }
+
+    /// The message without its HMAC, if the HMAC is right
+    fn verify<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], DecodeError> {
+        let Some(split) = bytes.len().checked_sub(MAC_LEN) else {
+            return Err(DecodeError::Unauthenticated);
+        };
+
+        let (message, received) = bytes.split_at(split);
+        let received = hex::decode(received).map_err(|_| DecodeError::Unauthenticated)?;
+        let mut mac = self.mac.clone();
+        mac.update(message);
+        // constant time compare, so the MAC can't be guessed byte by byte
+        mac.verify_slice(&received)
+            .map_err(|_| DecodeError::Unauthenticated)?;
+
+        Ok(message)
+    }
+
+    /// Our own messages never get here, so our id means a misconfigured bot
+    fn check_sender(&self, envelope: Envelope) -> Result<Envelope, DecodeError> {
+        if envelope.sender == self.bot_id {
+            return Err(DecodeError::DuplicateBotId);
+        }
+
+        Ok(envelope)
+    }
+
+    /// Only messages newer than the last one from their sender get through.
+    /// Timestamps order messages across restarts of the sender, when the
+    /// sequence starts over, and sequences order messages sent in the same
+    /// millisecond
+    fn check_fresh(&self, envelope: Envelope) -> Result<Envelope, DecodeError> {
+        if now_ms().saturating_sub(envelope.timestamp_ms) > self.max_age_ms {
+            return Err(DecodeError::Replayed);
+        }
+
+        let Ok(mut newest) = self.newest.lock() else {
+            return Err(DecodeError::Replayed);
+        };
+        let stamp = (envelope.timestamp_ms, envelope.sequence);
+        if newest
+            .get(&envelope.sender)
+            .is_some_and(|&seen| stamp <= seen)
+        {
+            return Err(DecodeError::Replayed);
+        }
+        newest.insert(envelope.sender.clone(), stamp);
+
+        Ok(envelope)
+    }
+}
+
+/// Traffic with one peer
+#[derive(Debug, Default)]
+struct PeerStats {
+    sent: u64,
+    send_failed: u64,
+    received: u64,
+    rejected: u64,
+}
+
+struct Peer {
+    /// as written in the config, or the address a discovered peer announced from
+    name: String,
+    addr: SocketAddr,
+    /// bot id, once we heard from the peer
+    id: Option<String>,
+    capabilities: Vec<String>,
+    /// found through an announcement rather than listed in the config
+    discovered: bool,
+    last_seen: Option<Instant>,
+    stale: bool,
+    stats: PeerStats,
+}
+
+impl Peer {
+    fn new(name: String, addr: SocketAddr, discovered: bool) -> Self {
+        Self {
+            name,
+            addr,
+            id: None,
+            capabilities: Vec::new(),
+            discovered,
+            last_seen: None,
+            stale: false,
+            stats: PeerStats::default(),
+        }
+    }
+
+    /// Configured peers are always sent to, since they may simply not have
+    /// started yet. Discovered ones only while they keep announcing themselves
+    fn reachable(&self) -> bool {
+        !(self.discovered && self.stale)
+    }
+}
+
+/// The swarm UDP socket, talks to the configured peers and the ones
+/// discovered on the multicast group
+pub struct SwarmSocket {
+    socket: UdpSocket,
+    codec: Codec,
+    capabilities: Vec<String>,
+    stale_after: Duration,
+    /// multicast group and the socket joined to it, when discovery is on
+    discovery: Option<(SocketAddr, UdpSocket)>,
+    /// locked only briefly, never across an await
+    peers: Mutex<Vec<Peer>>,
+    /// datagrams from addresses that are not peers
+    strangers: AtomicU64,
+}
+
+impl SwarmSocket {
+    /// Bind the socket and resolve every peer address, peers that don't
+    /// resolve are skipped
+    pub async fn bind(config: &SwarmConfig, addrs: &[String]) -> io::Result<Self> {
+        let socket = UdpSocket::bind(&config.bind_addr).await?;
+
+        let mut peers = Vec::new();
+        for name in addrs {
+            match lookup_host(name).await.map(|mut found| found.next()) {
+                Ok(Some(addr)) => peers.push(Peer::new(name.clone(), addr, false)),
+                Ok(None) => eprintln!("Swarm peer {} did not resolve, skipping it", name),
+                Err(e) => eprintln!("Swarm peer {} did not resolve, skipping it: {}", name, e),
+            }
+        }
+
+        let discovery = if config.discovery {
+            // without discovery we still work with the static peers
+            match join_group(&config.multicast_group).await {
+                Ok(joined) => {
+                    println!("Swarm discovery on {}", joined.0);
+                    Some(joined)
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to join swarm multicast group {}: {}",
+                        config.multicast_group, e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if peers.is_empty() && discovery.is_none() {
+            println!("No swarm peers configured, nobody will hear about our targets");
+        }
+
+        Ok(Self {
+            socket,
+            codec: Codec::new(config)?,
+            capabilities: config.capabilities.clone(),
+            stale_after: config.stale_after(),
+            discovery,
+            peers: Mutex::new(peers),
+            strangers: AtomicU64::new(0),
+        })
+    }
+
+    /// Send a payload to every reachable peer, a peer that can't be reached
+    /// doesn't stop the others
+    pub async fn broadcast(&self, payload: Payload) {
+        let message = self.codec.encode(payload);
+        let targets: Vec<SocketAddr> = self
+            .peers
+            .lock()
+            .map(|peers| {
+                peers
+                    .iter()
+                    .filter(|peer| peer.reachable())
+                    .map(|peer| peer.addr)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for addr in targets {
+            let sent = self.socket.send_to(&message, addr).await;
+            if let Err(e) = &sent {
+                eprintln!("Failed to send swarm message to {}: {}", addr, e);
+            }
+
+            self.with_peer(addr, |peer| match sent {
+                Ok(_) => peer.stats.sent += 1,
+                Err(_) => peer.stats.send_failed += 1,
+            });
+        }
+
+        self.report();
+    }
+
+    /// Tell the swarm we are alive, then mark the peers that stopped doing so stale.
+    /// Called every heartbeat interval
+    pub async fn heartbeat(&self) {
+        match &self.discovery {
+            // sent from the swarm socket, so peers learn the address we receive on
+            Some((group, _)) => {
+                let message = self.codec.encode(Payload::Announce {
+                    capabilities: self.capabilities.clone(),
+                });
+                if let Err(e) = self.socket.send_to(&message, *group).await {
+                    eprintln!("Failed to announce on {}: {}", group, e);
+                }
+            }
+            None => self.broadcast(Payload::Heartbeat).await,
+        }
+
+        if let Ok(mut peers) = self.peers.lock() {
+            for peer in peers.iter_mut() {
+                let silent = peer
+                    .last_seen
+                    .is_some_and(|seen| seen.elapsed() > self.stale_after);
+                if silent && !peer.stale {
+                    peer.stale = true;
+                    println!(
+                        "Swarm peer {} ({}) missed its heartbeats, marking it stale",
+                        peer.id.as_deref().unwrap_or("unknown"),
+                        peer.name
+                    );
+                }
+            }
+        }
+
+        self.report();
+    }
+
+    /// Wait for the next datagram from anyone but ourselves, on the swarm
+    /// socket or the multicast group, with the address it came from
+    pub async fn recv(&self) -> io::Result<(SocketAddr, Result<Envelope, DecodeError>)> {
+        loop {
+            let mut buf = [0; 2048];
+            let mut group_buf = [0; 2048];
+            let (bytes, from) = match &self.discovery {
+                Some((_, group)) => tokio::select! {
+                    received = self.socket.recv_from(&mut buf) => {
+                        let (len, from) = received?;
+                        (&buf[..len], from)
+                    }
+                    received = group.recv_from(&mut group_buf) => {
+                        let (len, from) = received?;
+                        (&group_buf[..len], from)
+                    }
+                },
+                None => {
+                    let (len, from) = self.socket.recv_from(&mut buf).await?;
+                    (&buf[..len], from)
+                }
+            };
+            let Some(decoded) = self.codec.decode(bytes) else {
+                continue;
+            };
+
+            self.update_peers(from, &decoded);
+            self.report();
+
+            return Ok((from, decoded));
+        }
+    }
+
+    fn update_peers(&self, from: SocketAddr, decoded: &Result<Envelope, DecodeError>) {
+        let Ok(mut peers) = self.peers.lock() else {
+            return;
+        };
+
+        let known = peers.iter().position(|peer| peer.addr == from);
+        let index = match (known, decoded) {
+            (Some(index), _) => index,
+            (
+                None,
+                Ok(Envelope {
+                    sender,
+                    payload: Payload::Announce { .. },
+                    ..
+                }),
+            ) => {
+                println!("Discovered swarm peer {} at {}", sender, from);
+                peers.push(Peer::new(from.to_string(), from, true));
+                peers.len() - 1
+            }
+            (None, _) => {
+                self.strangers.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let peer = &mut peers[index];
+        let Ok(envelope) = decoded else {
+            peer.stats.rejected += 1;
+            return;
+        };
+
+        peer.stats.received += 1;
+        peer.last_seen = Some(Instant::now());
+        peer.id = Some(envelope.sender.clone());
+        if let Payload::Announce { capabilities } = &envelope.payload {
+            peer.capabilities = capabilities.clone();
+        }
+        if peer.stale {
+            peer.stale = false;
+            println!("Swarm peer {} ({}) is back", envelope.sender, peer.name);
+        }
+    }
+
+    fn with_peer(&self, addr: SocketAddr, update: impl FnOnce(&mut Peer)) {
+        if let Ok(mut peers) = self.peers.lock()
+            && let Some(peer) = peers.iter_mut().find(|peer| peer.addr == addr)
+        {
+            update(peer);
+        }
+    }
+
+    fn report(&self) {
+        let peers = self
+            .peers
+            .lock()
+            .map(|peers| {
+                peers
+                    .iter()
+                    .map(|peer| SwarmPeer {
+                        addr: peer.name.clone(),
+                        id: peer.id.clone(),
+                        capabilities: peer.capabilities.clone(),
+                        discovered: peer.discovered,
+                        last_seen_secs: peer.last_seen.map(|seen| seen.elapsed().as_secs_f64()),
+                        stale: peer.stale,
+                        sent: peer.stats.sent,
+                        send_failed: peer.stats.send_failed,
+                        received: peer.stats.received,
+                        rejected: peer.stats.rejected,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        documenter::push_swarm(
+            self.codec.stats.accepted.load(Ordering::Relaxed),
+            self.codec.stats.rejected(),
+            self.strangers.load(Ordering::Relaxed),
+            peers,
+        );
+    }
+}
+
+/// Socket receiving on an IPv4 multicast group given as `group:port`
+async fn join_group(group: &str) -> io::Result<(SocketAddr, UdpSocket)> {
+    let addr = lookup_host(group)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address"))?;
+    let SocketAddr::V4(v4) = addr else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only IPv4 multicast groups are supported",
+        ));
+    };
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, v4.port())).await?;
+    socket.join_multicast_v4(*v4.ip(), Ipv4Addr::UNSPECIFIED)?;
+
+    Ok((addr, socket))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codec(bot_id: &str, key: &str) -> Codec {
+        Codec::new(&SwarmConfig {
+            bot_id: Some(bot_id.to_string()),
+            secret_key: Some(key.to_string()),
+            ..SwarmConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// An envelope signed by `codec` without going through `encode`
+    fn sign(codec: &Codec, envelope: &Envelope) -> Vec<u8> {
+        let mut bytes = serde_json::to_vec(envelope).unwrap();
+        let mut mac = codec.mac.clone();
+        mac.update(&bytes);
+        bytes.extend(hex::encode(mac.finalize().into_bytes()).as_bytes());
+        bytes
+    }
+
+    fn envelope(sender: &str) -> Envelope {
+        Envelope {
+            version: PROTOCOL_VERSION,
+            sender: sender.to_string(),
+            instance: 7,
+            sequence: 0,
+            timestamp_ms: now_ms(),
+            payload: Payload::Heartbeat,
+        }
+    }
+
+    fn target() -> Payload {
+        Payload::TargetFound {
+            class_name: "football".to_string(),
+            x: 1.5,
+            y: -2.0,
+        }
+    }
+
+    #[test]
+    fn signed_messages_round_trip() {
+        let (alice, bob) = (codec("alice", "key"), codec("bob", "key"));
+
+        let envelope = bob.decode(&alice.encode(target())).unwrap().unwrap();
+
+        assert_eq!(envelope.sender, "alice");
+        assert_eq!(envelope.payload, target());
+        assert_eq!(bob.stats.accepted.load(Ordering::Relaxed), 1);
+        assert_eq!(bob.stats.rejected(), 0);
+    }
+
+    #[test]
+    fn wrong_key_is_unauthenticated() {
+        let (alice, bob) = (codec("alice", "key"), codec("bob", "other key"));
+
+        let decoded = bob.decode(&alice.encode(target())).unwrap();
+
+        assert!(matches!(decoded, Err(DecodeError::Unauthenticated)));
+        assert_eq!(bob.stats.unauthenticated.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn tampered_messages_are_unauthenticated() {
+        let (alice, bob) = (codec("alice", "key"), codec("bob", "key"));
+        let message = alice.encode(target());
+        let message = String::from_utf8(message)
+            .unwrap()
+            .replace("1.5", "9.5")
+            .into_bytes();
+
+        let decoded = bob.decode(&message).unwrap();
+
+        assert!(matches!(decoded, Err(DecodeError::Unauthenticated)));
+    }
+
+    #[test]
+    fn unsigned_messages_are_unauthenticated() {
+        let bob = codec("bob", "key");
+        let unsigned = serde_json::to_vec(&envelope("alice")).unwrap();
+        let legacy = br#"{"x1":1.0,"y1":2.0,"z":0.0,"w":1.0}"#;
+
+        assert!(matches!(
+            bob.decode(&unsigned).unwrap(),
+            Err(DecodeError::Unauthenticated)
+        ));
+        assert!(matches!(
+            bob.decode(legacy).unwrap(),
+            Err(DecodeError::Unauthenticated)
+        ));
+        assert!(matches!(
+            bob.decode(b"").unwrap(),
+            Err(DecodeError::Unauthenticated)
+        ));
+    }
+
+    #[test]
+    fn replayed_messages_are_rejected() {
+        let (alice, bob) = (codec("alice", "key"), codec("bob", "key"));
+        let first = alice.encode(target());
+        let second = alice.encode(target());
+
+        assert!(bob.decode(&first).unwrap().is_ok());
+        assert!(bob.decode(&second).unwrap().is_ok());
+        assert!(matches!(
+            bob.decode(&first).unwrap(),
+            Err(DecodeError::Replayed)
+        ));
+        assert_eq!(bob.stats.replayed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn old_messages_are_rejected() {
+        let bob = codec("bob", "key");
+        let mut old = envelope("alice");
+        old.timestamp_ms -= 60_000;
+
+        assert!(matches!(
+            bob.decode(&sign(&bob, &old)).unwrap(),
+            Err(DecodeError::Replayed)
+        ));
+    }
+
+    #[test]
+    fn other_versions_are_unsupported() {
+        let bob = codec("bob", "key");
+        let mut future = envelope("alice");
+        future.version = PROTOCOL_VERSION + 1;
+
+        assert!(matches!(
+            bob.decode(&sign(&bob, &future)).unwrap(),
+            Err(DecodeError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn our_own_messages_are_skipped_without_counting() {
+        let alice = codec("alice", "key");
+
+        assert!(alice.decode(&alice.encode(target())).is_none());
+        assert_eq!(alice.stats.accepted.load(Ordering::Relaxed), 0);
+        assert_eq!(alice.stats.rejected(), 0);
+    }
+
+    #[test]
+    fn another_bot_with_our_id_is_rejected() {
+        let (first, second) = (codec("alice", "key"), codec("alice", "key"));
+
+        assert!(matches!(
+            second.decode(&first.encode(target())).unwrap(),
+            Err(DecodeError::DuplicateBotId)
+        ));
+    }
+}
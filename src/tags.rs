@@ -0,0 +1,175 @@
+use apriltag::{Detector, Family, Image, TagParams};
+use image::GrayImage;
+use serde::Deserialize;
+
+use crate::camera::CameraIntrinsics;
+use crate::error::Error;
+use crate::pipeline::FrameInfo;
+use crate::ranging::{RangeBearing, RangingConfig};
+
+/// AprilTag section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TagConfig {
+    pub enabled: bool,
+    /// tag families to look for, e.g. tag36h11, tag25h9, tagStandard41h12
+    pub families: Vec<String>,
+    /// edge length of the black square of the tag, meters
+    pub tag_size: f64,
+    /// bit errors the decoder is allowed to correct
+    pub max_hamming: usize,
+    /// detect on an image downscaled by this factor, faster but shorter range
+    pub decimation: f32,
+    pub threads: u8,
+}
+
+impl Default for TagConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            families: vec!["tag36h11".to_string()],
+            tag_size: 0.1,
+            max_hamming: 1,
+            decimation: 2.0,
+            threads: 1,
+        }
+    }
+}
+
+/// 6-DoF pose of a tag in the camera frame (x right, y down, z forward)
+#[derive(Debug, Clone)]
+pub struct TagPose {
+    /// row major rotation of the tag frame
+    pub rotation: [[f64; 3]; 3],
+    /// tag center, meters
+    pub translation: [f64; 3],
+    /// the same position relative to base_link
+    pub range_bearing: RangeBearing,
+}
+
+impl TagPose {
+    /// Turn of the tag about the camera's vertical axis, 0 when it squarely
+    /// faces the camera
+    pub fn facing(&self) -> f64 {
+        self.rotation[0][2].atan2(self.rotation[2][2])
+    }
+}
+
+/// A decoded tag in one camera frame
+#[derive(Debug, Clone)]
+pub struct TagDetection {
+    pub family: String,
+    pub id: u32,
+    /// bits that had to be corrected
+    pub hamming: u32,
+    pub decision_margin: f32,
+    /// pixels
+    pub center: (f64, f64),
+    pub corners: [(f64, f64); 4],
+    /// None if the pose could not be solved
+    pub pose: Option<TagPose>,
+    /// the frame the tag was found in
+    pub frame: FrameInfo,
+}
+
+pub struct TagDetector {
+    // one detector per family so every detection knows where it came from
+    detectors: Vec<(String, Detector)>,
+    params: TagParams,
+    camera_offset_x: f64,
+}
+
+impl TagDetector {
+    pub fn new(
+        config: &TagConfig,
+        intrinsics: &CameraIntrinsics,
+        ranging: &RangingConfig,
+    ) -> Result<Self, Error> {
+        let mut detectors = Vec::new();
+
+        for name in &config.families {
+            let family: Family = name
+                .parse()
+                .map_err(|_| anyhow::anyhow!("unknown AprilTag family {}", name))?;
+            let mut detector = Detector::builder()
+                .add_family_bits(family, config.max_hamming)
+                .build()
+                .map_err(|e| anyhow::anyhow!("failed to build {} detector: {:?}", name, e))?;
+            detector.set_decimation(config.decimation);
+            detector.set_thread_number(config.threads);
+
+            detectors.push((name.clone(), detector));
+        }
+
+        Ok(Self {
+            detectors,
+            params: TagParams {
+                tagsize: config.tag_size,
+                fx: intrinsics.fx,
+                fy: intrinsics.fy,
+                cx: intrinsics.cx,
+                cy: intrinsics.cy,
+            },
+            camera_offset_x: ranging.camera_offset_x,
+        })
+    }
+
+    /// Find every tag in a grayscale frame
+    pub fn detect(&mut self, img: &GrayImage, frame: FrameInfo) -> Vec<TagDetection> {
+        let Some(image) = to_apriltag_image(img) else {
+            return Vec::new();
+        };
+
+        let mut tags = Vec::new();
+        for (family, detector) in &mut self.detectors {
+            for detection in detector.detect(&image) {
+                let [cx, cy] = detection.center();
+                let pose = detection.estimate_tag_pose(&self.params).and_then(|pose| {
+                    let r = pose.rotation().data();
+                    let t = pose.translation().data();
+                    (r.len() == 9 && t.len() == 3).then(|| TagPose {
+                        rotation: [[r[0], r[1], r[2]], [r[3], r[4], r[5]], [r[6], r[7], r[8]]],
+                        translation: [t[0], t[1], t[2]],
+                        range_bearing: to_base_link(t, self.camera_offset_x),
+                    })
+                });
+
+                tags.push(TagDetection {
+                    family: family.clone(),
+                    id: detection.id() as u32,
+                    hamming: detection.hamming() as u32,
+                    decision_margin: detection.decision_margin(),
+                    center: (cx, cy),
+                    corners: detection.corners().map(|[x, y]| (x, y)),
+                    pose,
+                    frame,
+                });
+            }
+        }
+
+        tags
+    }
+}
+
+fn to_apriltag_image(img: &GrayImage) -> Option<Image> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    // 96 byte rows is what the C library allocates by default
+    let mut image = Image::zeros_with_alignment(width, height, 96)?;
+
+    for (x, y, pixel) in img.enumerate_pixels() {
+        image[(x as usize, y as usize)] = pixel.0[0];
+    }
+
+    Some(image)
+}
+
+// camera z is forward and x is right, ROS x is forward and y is left
+fn to_base_link(translation: &[f64], camera_offset_x: f64) -> RangeBearing {
+    let forward = translation[2] + camera_offset_x;
+    let left = -translation[0];
+
+    RangeBearing {
+        range: forward.hypot(left),
+        bearing: left.atan2(forward),
+    }
+}
@@ -0,0 +1,320 @@
+use serde::Deserialize;
+
+use crate::XyXy;
+use crate::yolo::Detection;
+
+/// Tracker section of the config json file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TrackerConfig {
+    /// minimum overlap between a prediction and a detection to match them
+    pub iou_threshold: f32,
+    /// frames a track has to be matched in before it is reported
+    pub min_hits: u32,
+    /// frames a track survives without a match before it is dropped
+    pub max_age: u32,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            iou_threshold: 0.3,
+            min_hits: 3,
+            max_age: 5,
+        }
+    }
+}
+
+// process / measurement noise of the constant velocity model, in pixels
+const PROCESS_NOISE: f32 = 1.0;
+const MEASUREMENT_NOISE: f32 = 10.0;
+
+/// Constant velocity Kalman filter over a single box coordinate, the box is
+/// tracked as four of these (center x, center y, width, height)
+#[derive(Debug, Clone)]
+struct Kalman {
+    // position, velocity (per frame)
+    x: [f32; 2],
+    p: [[f32; 2]; 2],
+}
+
+impl Kalman {
+    fn new(position: f32) -> Self {
+        Self {
+            x: [position, 0.0],
+            // we know nothing about the velocity yet
+            p: [[MEASUREMENT_NOISE, 0.0], [0.0, 1000.0]],
+        }
+    }
+
+    fn predict(&mut self) {
+        let [pos, vel] = self.x;
+        self.x = [pos + vel, vel];
+
+        // P = F P F^T + Q with F = [[1, 1], [0, 1]]
+        let [[p00, p01], [p10, p11]] = self.p;
+        self.p = [
+            [p00 + p01 + p10 + p11 + PROCESS_NOISE, p01 + p11],
+            [p10 + p11, p11 + PROCESS_NOISE],
+        ];
+    }
+
+    fn update(&mut self, measurement: f32) {
+        let [[p00, p01], [p10, p11]] = self.p;
+        let innovation = measurement - self.x[0];
+        let s = p00 + MEASUREMENT_NOISE;
+        let k = [p00 / s, p10 / s];
+
+        self.x = [self.x[0] + k[0] * innovation, self.x[1] + k[1] * innovation];
+        self.p = [
+            [(1.0 - k[0]) * p00, (1.0 - k[0]) * p01],
+            [p10 - k[1] * p00, p11 - k[1] * p01],
+        ];
+    }
+}
+
+/// An object followed across frames
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub id: u64,
+    /// last detection matched to this track
+    pub detection: Detection,
+    /// filtered box after the last update
+    pub bbox: XyXy,
+    /// box the motion model expected before the last update
+    pub predicted: XyXy,
+    /// frames since the track was created
+    pub age: u32,
+    /// frames the track was matched to a detection
+    pub hits: u32,
+    /// frames since the last match
+    pub misses: u32,
+    filters: [Kalman; 4],
+}
+
+impl Track {
+    fn new(id: u64, detection: Detection) -> Self {
+        let [cx, cy, w, h] = to_cxcywh(detection.bbox);
+
+        Self {
+            id,
+            bbox: detection.bbox,
+            predicted: detection.bbox,
+            detection,
+            age: 0,
+            hits: 1,
+            misses: 0,
+            filters: [
+                Kalman::new(cx),
+                Kalman::new(cy),
+                Kalman::new(w),
+                Kalman::new(h),
+            ],
+        }
+    }
+
+    fn predict(&mut self) {
+        self.filters.iter_mut().for_each(Kalman::predict);
+        self.predicted = self.state();
+        self.age += 1;
+        self.misses += 1;
+    }
+
+    fn update(&mut self, detection: Detection) {
+        let measurement = to_cxcywh(detection.bbox);
+        for (filter, z) in self.filters.iter_mut().zip(measurement) {
+            filter.update(z);
+        }
+
+        self.bbox = self.state();
+        self.detection = detection;
+        self.hits += 1;
+        self.misses = 0;
+    }
+
+    fn state(&self) -> XyXy {
+        let [cx, cy, w, h] = self.filters.each_ref().map(|f| f.x[0]);
+        (cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0)
+    }
+}
+
+/// SORT style multi object tracker, boxes are matched to tracks greedily by
+/// IoU against the Kalman prediction
+#[derive(Debug)]
+pub struct Tracker {
+    config: TrackerConfig,
+    tracks: Vec<Track>,
+    next_id: u64,
+}
+
+impl Tracker {
+    pub fn new(config: &TrackerConfig) -> Self {
+        Self {
+            config: config.clone(),
+            tracks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Feed the detections of one frame, returns the confirmed tracks that
+    /// were matched in this frame
+    pub fn update(&mut self, detections: Vec<Detection>) -> Vec<Track> {
+        self.tracks.iter_mut().for_each(Track::predict);
+
+        // every same class (track, detection) pair above the threshold, best first
+        let mut pairs = Vec::new();
+        for (t, track) in self.tracks.iter().enumerate() {
+            for (d, detection) in detections.iter().enumerate() {
+                if track.detection.class_id != detection.class_id {
+                    continue;
+                }
+
+                let overlap = iou(track.predicted, detection.bbox);
+                if overlap >= self.config.iou_threshold {
+                    pairs.push((overlap, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut detections: Vec<Option<Detection>> = detections.into_iter().map(Some).collect();
+        let mut matched = vec![false; self.tracks.len()];
+
+        for (_, t, d) in pairs {
+            if matched[t] {
+                continue;
+            }
+
+            if let Some(detection) = detections[d].take() {
+                self.tracks[t].update(detection);
+                matched[t] = true;
+            }
+        }
+
+        // whatever is left starts a new track
+        for detection in detections.into_iter().flatten() {
+            self.tracks.push(Track::new(self.next_id, detection));
+            self.next_id += 1;
+        }
+
+        let max_age = self.config.max_age;
+        self.tracks.retain(|track| track.misses <= max_age);
+
+        self.tracks
+            .iter()
+            .filter(|track| track.misses == 0 && track.hits >= self.config.min_hits)
+            .cloned()
+            .collect()
+    }
+}
+
+fn to_cxcywh((x1, y1, x2, y2): XyXy) -> [f32; 4] {
+    [(x1 + x2) / 2.0, (y1 + y2) / 2.0, x2 - x1, y2 - y1]
+}
+
+/// intersection over union of two boxes
+pub fn iou(a: XyXy, b: XyXy) -> f32 {
+    let w = (a.2.min(b.2) - a.0.max(b.0)).max(0.0);
+    let h = (a.3.min(b.3) - a.1.max(b.1)).max(0.0);
+    let intersection = w * h;
+    let union = (a.2 - a.0) * (a.3 - a.1) + (b.2 - b.0) * (b.3 - b.1) - intersection;
+
+    if union <= 0.0 {
+        0.0
+    } else {
+        intersection / union
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::FrameInfo;
+    use image::DynamicImage;
+
+    fn detection(bbox: XyXy) -> Detection {
+        Detection {
+            bbox,
+            class_id: 0,
+            class_name: "charm".to_string(),
+            confidence: 0.9,
+            frame: FrameInfo::new(0, &DynamicImage::new_rgb8(1, 1)),
+            mask: None,
+        }
+    }
+
+    // a 40x40 box with its left edge at x
+    fn square(x: f32, y: f32) -> XyXy {
+        (x, y, x + 40.0, y + 40.0)
+    }
+
+    #[test]
+    fn track_keeps_its_id_while_moving() {
+        let mut tracker = Tracker::new(&TrackerConfig::default());
+
+        for frame in 0..10 {
+            let tracks = tracker.update(vec![detection(square(frame as f32 * 8.0, 50.0))]);
+
+            // only reported once it has been seen min_hits times
+            if frame < 2 {
+                assert!(tracks.is_empty());
+            } else {
+                assert_eq!(tracks.len(), 1);
+                assert_eq!(tracks[0].id, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn track_dies_after_max_age_misses() {
+        let config = TrackerConfig::default();
+        let mut tracker = Tracker::new(&config);
+
+        for _ in 0..3 {
+            tracker.update(vec![detection(square(100.0, 100.0))]);
+        }
+        for _ in 0..config.max_age {
+            assert!(tracker.update(Vec::new()).is_empty());
+        }
+        // still alive, one more miss drops it
+        assert_eq!(tracker.tracks.len(), 1);
+        tracker.update(Vec::new());
+        assert!(tracker.tracks.is_empty());
+
+        // the same box again is a new object
+        tracker.update(vec![detection(square(100.0, 100.0))]);
+        assert_eq!(tracker.tracks[0].id, 1);
+    }
+
+    #[test]
+    fn crossing_tracks_keep_their_ids() {
+        let mut tracker = Tracker::new(&TrackerConfig::default());
+        let mut last = Vec::new();
+
+        // one box moves right and the other left, they overlap in the middle
+        for frame in 0..20 {
+            let step = frame as f32 * 10.0;
+            let tracks = tracker.update(vec![
+                detection(square(step, 100.0)),
+                detection(square(200.0 - step, 110.0)),
+            ]);
+            if !tracks.is_empty() {
+                last = tracks;
+            }
+        }
+
+        assert_eq!(last.len(), 2);
+        let rightwards = last.iter().find(|t| t.id == 0).unwrap();
+        let leftwards = last.iter().find(|t| t.id == 1).unwrap();
+        assert!(rightwards.bbox.0 > leftwards.bbox.0);
+        assert!(rightwards.bbox.1 < leftwards.bbox.1);
+    }
+
+    #[test]
+    fn iou_of_identical_and_disjoint_boxes() {
+        assert!((iou(square(0.0, 0.0), square(0.0, 0.0)) - 1.0).abs() < 1e-6);
+        assert_eq!(iou(square(0.0, 0.0), square(100.0, 0.0)), 0.0);
+        // half overlapping: 20x40 of 60x40
+        assert!((iou(square(0.0, 0.0), square(20.0, 0.0)) - 1.0 / 3.0).abs() < 1e-6);
+    }
+}
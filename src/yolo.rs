@@ -1,49 +1,314 @@
 // yolo.rs
-use image::DynamicImage;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage};
 use serde::Deserialize;
 use usls::{Device, Nms, Options, Vision, YOLOTask, YOLOVersion, models::YOLO};
 
 use std::path::Path;
+use std::sync::Arc;
 use std::{fs::File, io::BufReader};
 
+use crate::camera::{CameraConfig, CameraIntrinsics};
+use crate::color::ColorConfig;
+use crate::coverage::CoverageConfig;
+use crate::detector::DetectorKind;
+use crate::frame_source::SourceConfig;
+use crate::frontier::ExplorationConfig;
+use crate::grid::MapConfig;
+use crate::home::HomeConfig;
+use crate::pipeline::{FrameInfo, VisionConfig};
+use crate::planner::PlannerConfig;
+use crate::preview::PreviewConfig;
+use crate::ranging::RangingConfig;
+use crate::reload::ReloadConfig;
+use crate::servo::ServoConfig;
+use crate::swarm::SwarmConfig;
+use crate::tags::TagConfig;
+use crate::tracker::TrackerConfig;
 use crate::{XyXy, error::Error};
 
-const YOLOV8_CLASS_LABELS: [&str; 5] = [
-    "football",
-    "green cone",
-    "purple cone",
-    "red cone",
-    "yellow cone",
-];
-
 /// Config json file data structure
 #[derive(Debug, Clone, Deserialize)]
 #[allow(dead_code)]
 pub struct ModelConfig {
     /// ONNX model absolute path
     pub model_path: String,
-    /// array of class names
+    /// YOLO generation the model was exported from
+    #[serde(default)]
+    pub yolo_version: YoloVersion,
+    /// plain boxes or boxes with instance masks
+    #[serde(default)]
+    pub yolo_task: YoloTask,
+    /// array of class names, leave empty to use the names stored in the model
+    #[serde(default)]
     pub class_names: Vec<String>,
-    /// model input image size
+    /// classes the bot tracks, exact names or `*` wildcards like `*cone`,
+    /// empty means every class is a target
+    #[serde(default)]
+    pub target_classes: Vec<String>,
+    /// detector the camera pipeline runs
+    #[serde(default)]
+    pub detector: DetectorKind,
+    /// HSV ranges of the color detector
+    #[serde(default)]
+    pub color: ColorConfig,
+    /// model input image size, the size the model was exported at
     pub input_size: i32,
+    /// largest input size for models exported with dynamic shapes
+    #[serde(default)]
+    pub max_input_size: Option<i32>,
+    /// largest number of frames run through the model at once
+    #[serde(default = "default_batch_size")]
+    pub batch_size: i32,
+    /// pad frames to a square keeping their aspect, stretch them otherwise
+    #[serde(default = "default_letterbox")]
+    pub letterbox: bool,
+    /// detections below this confidence are dropped
+    #[serde(default = "default_confidence_threshold")]
+    pub confidence_threshold: f32,
+    /// boxes overlapping more than this are merged by NMS
+    #[serde(default = "default_nms_threshold")]
+    pub nms_threshold: f32,
     /// swarm addresses
     pub addr: Vec<String>,
+    /// identity of this bot in the swarm
+    #[serde(default)]
+    pub swarm: SwarmConfig,
+    /// occupancy grid settings
+    #[serde(default)]
+    pub map: MapConfig,
+    /// path planner settings
+    #[serde(default)]
+    pub planner: PlannerConfig,
+    /// search strategy and camera coverage settings
+    #[serde(default)]
+    pub coverage: CoverageConfig,
+    /// frontier exploration settings
+    #[serde(default)]
+    pub exploration: ExplorationConfig,
+    /// return to start settings
+    #[serde(default)]
+    pub home: HomeConfig,
+    /// multi object tracker settings
+    #[serde(default)]
+    pub tracker: TrackerConfig,
+    /// camera intrinsics used for metric estimates
+    #[serde(default)]
+    pub intrinsics: CameraIntrinsics,
+    /// file written by the calibrate subcommand, overrides `intrinsics` when present
+    #[serde(default = "default_calibration_path")]
+    pub calibration_path: String,
+    /// range and bearing estimation settings
+    #[serde(default)]
+    pub ranging: RangingConfig,
+    /// AprilTag detection settings
+    #[serde(default)]
+    pub tags: TagConfig,
+    /// webcam device and stream format
+    #[serde(default)]
+    pub camera: CameraConfig,
+    /// where frames come from, the webcam unless configured otherwise
+    #[serde(default)]
+    pub source: SourceConfig,
+    /// annotated debug frames
+    #[serde(default)]
+    pub preview: PreviewConfig,
+    /// frame dropping and pipeline metrics
+    #[serde(default)]
+    pub vision: VisionConfig,
+    /// visual servo used to approach targets
+    #[serde(default)]
+    pub servo: ServoConfig,
+    /// swapping the model and thresholds at runtime
+    #[serde(default)]
+    pub reload: ReloadConfig,
+}
+
+/// YOLO generation of the ONNX model, they differ in their output layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YoloVersion {
+    V5,
+    #[default]
+    V8,
+    V11,
+}
+
+impl From<YoloVersion> for YOLOVersion {
+    fn from(version: YoloVersion) -> Self {
+        match version {
+            YoloVersion::V5 => YOLOVersion::V5,
+            YoloVersion::V8 => YOLOVersion::V8,
+            YoloVersion::V11 => YOLOVersion::V11,
+        }
+    }
+}
+
+/// What the ONNX model was trained to predict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YoloTask {
+    #[default]
+    Detect,
+    /// instance segmentation, every box comes with a mask
+    Segment,
+}
+
+impl From<YoloTask> for YOLOTask {
+    fn from(task: YoloTask) -> Self {
+        match task {
+            YoloTask::Detect => YOLOTask::Detect,
+            YoloTask::Segment => YOLOTask::Segment,
+        }
+    }
+}
+
+fn default_confidence_threshold() -> f32 {
+    0.9
+}
+
+fn default_batch_size() -> i32 {
+    1
+}
+
+fn default_letterbox() -> bool {
+    true
+}
+
+fn default_nms_threshold() -> f32 {
+    0.45
+}
+
+fn default_calibration_path() -> String {
+    "../data/calibration.json".to_string()
+}
+
+impl ModelConfig {
+    /// does the current mission care about this detection
+    pub fn is_target(&self, detection: &Detection) -> bool {
+        self.target_classes.is_empty()
+            || self
+                .target_classes
+                .iter()
+                .any(|pattern| class_matches(pattern, &detection.class_name))
+    }
+}
+
+fn class_matches(pattern: &str, class_name: &str) -> bool {
+    match (pattern.strip_prefix('*'), pattern.strip_suffix('*')) {
+        (Some(suffix), _) => class_name.ends_with(suffix),
+        (_, Some(prefix)) => class_name.starts_with(prefix),
+        _ => class_name == pattern,
+    }
 }
 
 #[derive(Debug)]
 pub struct Model {
     pub model: YOLO,
+    /// class names indexed by class id
+    pub class_names: Vec<String>,
+    pub confidence_threshold: f32,
+    /// side of the square the model runs on
+    pub input_size: u32,
+    pub letterbox: bool,
 }
 
+/// A single object found in a camera frame
+#[derive(Debug, Clone)]
+pub struct Detection {
+    /// bounding box in pixels, (x1, y1, x2, y2)
+    pub bbox: XyXy,
+    pub class_id: usize,
+    pub class_name: String,
+    pub confidence: f32,
+    /// the frame the object was found in
+    pub frame: FrameInfo,
+    /// instance mask, only segmentation models produce one
+    pub mask: Option<Arc<ObjectMask>>,
+}
+
+/// Instance segmentation mask of one object, cropped to its bounding box
+#[derive(Debug)]
+pub struct ObjectMask {
+    /// frame pixel of the top left corner of the crop
+    pub origin: (u32, u32),
+    /// nonzero where the object is
+    pub pixels: GrayImage,
+}
+
+impl ObjectMask {
+    /// Crop a full frame mask to a box, scaling it by (sx, sy) into frame pixels
+    fn from_frame_mask(
+        mask: &GrayImage,
+        (x1, y1, x2, y2): XyXy,
+        (sx, sy): (f32, f32),
+    ) -> Option<Self> {
+        let clamp = |v: f32, max: u32| (v.max(0.0) as u32).min(max);
+        let (x1, y1) = (clamp(x1, mask.width()), clamp(y1, mask.height()));
+        let (x2, y2) = (
+            clamp(x2.ceil(), mask.width()),
+            clamp(y2.ceil(), mask.height()),
+        );
+        if x2 <= x1 || y2 <= y1 {
+            return None;
+        }
+
+        let crop = imageops::crop_imm(mask, x1, y1, x2 - x1, y2 - y1).to_image();
+        let width = ((x2 - x1) as f32 * sx).round().max(1.0) as u32;
+        let height = ((y2 - y1) as f32 * sy).round().max(1.0) as u32;
+        let pixels = if crop.dimensions() == (width, height) {
+            crop
+        } else {
+            imageops::resize(&crop, width, height, FilterType::Nearest)
+        };
+
+        Some(Self {
+            origin: ((x1 as f32 * sx) as u32, (y1 as f32 * sy) as u32),
+            pixels,
+        })
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        self.pixels.get_pixel(x, y).0[0] > 127
+    }
+
+    /// Frame pixel where the object touches the floor, the horizontal
+    /// center of the mask on its lowest row
+    pub fn contact_point(&self) -> Option<(f32, f32)> {
+        let (width, height) = self.pixels.dimensions();
+        let (mut sum_x, mut count, mut bottom) = (0u64, 0u64, None);
+
+        for y in 0..height {
+            for x in (0..width).filter(|&x| self.contains(x, y)) {
+                sum_x += x as u64;
+                count += 1;
+                bottom = Some(y);
+            }
+        }
+
+        let bottom = bottom?;
+        let center_x = sum_x as f32 / count as f32;
+
+        Some((
+            self.origin.0 as f32 + center_x,
+            (self.origin.1 + bottom + 1) as f32,
+        ))
+    }
+}
+
+/// config json file, change the path if needed
+pub const CONFIG_PATH: &str = "../data/config.json";
+
 /// load ModelConfig json config file
 pub fn load_model_file() -> Result<ModelConfig, Error> {
-    // change the path if needed
-    let file = File::open("../data/config.json")?;
+    let file = File::open(CONFIG_PATH)?;
     let reader = BufReader::new(file);
     let model_config: ModelConfig = serde_json::from_reader(reader)?;
+    model_config.coverage.validate()?;
 
+    // a missing model is not fatal, the color detector takes over
     if !Path::new(&model_config.model_path).exists() {
-        return Err(Error::OnnxModelFileNotFound);
+        println!("ONNX model file {} not found", model_config.model_path);
     }
 
     Ok(model_config)
@@ -51,33 +316,124 @@ pub fn load_model_file() -> Result<ModelConfig, Error> {
 
 /// Load the model to put in the
 pub fn load_model(model_config: ModelConfig) -> Result<Model, Error> {
-    let options = Options::new()
-        .with_model(&model_config.model_path)
-        .expect("model should load")
-        .with_yolo_version(YOLOVersion::V8)
-        .with_yolo_task(YOLOTask::Detect)
+    if !Path::new(&model_config.model_path).exists() {
+        return Err(Error::OnnxModelFileNotFound);
+    }
+
+    let size = model_config.input_size;
+    let max_size = model_config.max_input_size.unwrap_or(size);
+    // yolo downsamples by 32, anything else can't be fed to the model
+    if size <= 0 || size % 32 != 0 || max_size < size || max_size % 32 != 0 {
+        return Err(anyhow::anyhow!(
+            "Model input size {} (max {}) has to be a positive multiple of 32",
+            size,
+            max_size
+        )
+        .into());
+    }
+
+    let mut options = Options::new()
+        .with_model(&model_config.model_path)?
+        .with_yolo_version(model_config.yolo_version.into())
+        .with_yolo_task(model_config.yolo_task.into())
         .with_device(Device::Cpu(0))
-        .with_ixx(0, 0, (1, 1, 4).into())
-        .with_ixx(0, 2, (0, 480, 480).into())
-        .with_ixx(0, 3, (0, 480, 480).into())
-        .with_confs(&[0.25])
-        .with_names(&YOLOV8_CLASS_LABELS);
+        .with_ixx(0, 0, (1, 1, model_config.batch_size.max(1)).into())
+        .with_ixx(0, 2, (size, size, max_size).into())
+        .with_ixx(0, 3, (size, size, max_size).into())
+        .with_confs(&[model_config.confidence_threshold])
+        .with_iou(model_config.nms_threshold);
+
+    // without names in the config usls falls back to the model metadata
+    if !model_config.class_names.is_empty() {
+        let names: Vec<&str> = model_config
+            .class_names
+            .iter()
+            .map(String::as_str)
+            .collect();
+        options = options.with_names(&names);
+    }
 
     let model = YOLO::new(options)?;
 
-    println!("Yolo ONNX model loaded.");
+    println!(
+        "Yolo {:?} {:?} ONNX model loaded, {}x{} input, batch {}",
+        model_config.yolo_version, model_config.yolo_task, size, size, model_config.batch_size
+    );
 
-    Ok(Model { model })
+    Ok(Model {
+        model,
+        class_names: model_config.class_names,
+        confidence_threshold: model_config.confidence_threshold,
+        input_size: size as u32,
+        letterbox: model_config.letterbox,
+    })
 }
 
-/// Yolo inference on pre allocated res_box,
-pub fn detect(model: &mut YOLO, img: &[DynamicImage]) -> Option<XyXy> {
-    let popped = model.run(img).ok()?.pop()?;
+/// Yolo inference on one frame, returns every detection above the confidence
+/// threshold sorted by confidence. One image per call, so every box is scaled
+/// by the frame it came from
+pub fn detect(model: &mut Model, img: &DynamicImage, frame: FrameInfo) -> Vec<Detection> {
+    // usls letterboxes on its own, stretching is done here and undone on the boxes
+    let stretched;
+    let (input, (sx, sy)) = if model.letterbox {
+        (img, (1.0, 1.0))
+    } else {
+        let size = model.input_size;
+        stretched = img.resize_exact(size, size, FilterType::Triangle);
+        let scale = (
+            img.width() as f32 / size as f32,
+            img.height() as f32 / size as f32,
+        );
+        (&stretched, scale)
+    };
+
+    let Some(popped) = model
+        .model
+        .run(std::slice::from_ref(input))
+        .ok()
+        .and_then(|mut ys| ys.pop())
+    else {
+        return Vec::new();
+    };
 
-    // convert option to error, inference failed error
-    popped
+    // usls hands out one mask per box, in box order
+    let masks = popped
+        .masks()
+        .filter(|masks| Some(masks.len()) == popped.bboxes().map(Vec::len));
+
+    let mut detections: Vec<Detection> = popped
         .bboxes()
-        .and_then(|x| x.first())
-        .filter(|&bbox| bbox.confidence() >= 0.9)
-        .map(usls::Bbox::xyxy)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_, bbox)| bbox.confidence() >= model.confidence_threshold)
+        .map(|(n, bbox)| {
+            let class_id = bbox.id().max(0) as usize;
+            let class_name = model
+                .class_names
+                .get(class_id)
+                .or(bbox.name())
+                .cloned()
+                .unwrap_or_else(|| format!("class {}", class_id));
+
+            let mask = masks
+                .and_then(|masks| masks.get(n))
+                .and_then(|mask| ObjectMask::from_frame_mask(mask.mask(), bbox.xyxy(), (sx, sy)))
+                .map(Arc::new);
+
+            let (x1, y1, x2, y2) = bbox.xyxy();
+            Detection {
+                bbox: (x1 * sx, y1 * sy, x2 * sx, y2 * sy),
+                class_id,
+                class_name,
+                confidence: bbox.confidence(),
+                frame,
+                mask,
+            }
+        })
+        .collect();
+
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    detections
 }